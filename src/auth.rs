@@ -1,26 +1,61 @@
-use crate::blog::STORE_PATH;
+use crate::store::Store;
 use argon2::{PasswordHasher, PasswordVerifier};
-use rand::SeedableRng;
+use hmac::Mac;
+use rand::{RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
 use std::collections::HashMap;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use std::sync::Arc;
 
 // dummy unit tuple so `Auth`s can't be instantiated outside of this file
 pub struct Auth(());
 
-async fn read_logins() -> std::io::Result<HashMap<String, String>> {
-    let file = tokio::fs::File::open(std::path::Path::new(STORE_PATH).join("logins.txt")).await?;
-    let reader = tokio::io::BufReader::new(file);
-    let mut reader = reader.lines();
+/// Everything kept about a user's password
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credential {
+    /// argon2, as a PHC string
+    pub hash: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// when the password was last changed
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// wrong passwords since the last right one
+    pub failed_attempts: u32,
+    pub last_failed_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub totp: Option<Totp>,
+}
 
-    let mut logins = HashMap::new();
-    while let Some(line) = reader.next_line().await? {
-        let Some((username, hash)) = line.split_once('\t') else {
-            continue;
-        };
-        logins.insert(username.to_owned(), hash.to_owned());
-    }
+/// An authenticator app giving RFC 6238 codes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Totp {
+    /// base32, the way it's given to the app
+    pub secret: String,
+    /// `None` while enrolling. Codes are only asked for once one has been
+    /// confirmed.
+    pub confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// the newest time step a code was accepted for, so each code only works once
+    pub last_used_step: Option<u64>,
+    /// sha256 of each recovery code that hasn't been used yet
+    pub recovery_codes: Vec<String>,
+}
 
-    Ok(logins)
+/// What checking a login came to
+pub enum Login {
+    Valid(Auth),
+    /// wrong username, password or code, or too many wrong ones lately
+    Invalid,
+    /// the password was right, but the user has an authenticator app and
+    /// didn't give a code from it
+    CodeRequired,
+}
+
+/// The store's credentials, cached in memory as they're read. Writes go
+/// through one lock, so checking whether a username is taken and taking it
+/// can't be interleaved.
+#[derive(Debug)]
+pub struct Credentials {
+    store: Arc<dyn Store>,
+    cache: tokio::sync::Mutex<HashMap<String, Credential>>,
 }
 
 fn hash_password(password: &str) -> argon2::password_hash::Result<String> {
@@ -42,38 +77,94 @@ fn verify_password(password: &str, hash: &str) -> argon2::password_hash::Result<
 }
 
 impl Auth {
-    /// `Ok(Some(Auth))` if valid, `Ok(None)` if invalid, `Err` if logins.txt
-    /// could not be read/argon2 verifying failed
+    /// Skips checking anything, so tests can log in without a password
+    #[cfg(test)]
+    pub fn unchecked() -> Auth {
+        Auth(())
+    }
+
+    /// Checks the password and, if the user has an authenticator app, `code`,
+    /// which can also be one of their recovery codes. `Err` if the credentials
+    /// could not be read/argon2 verifying failed.
     pub async fn validate(
+        credentials: &Credentials,
         username: &str,
         password: String,
-    ) -> Result<Option<Auth>, Box<dyn std::error::Error>> {
-        let logins_file = read_logins().await?;
+        code: Option<String>,
+    ) -> Result<Login, Box<dyn std::error::Error>> {
+        if !Self::check_password(credentials, username, password).await? {
+            return Ok(Login::Invalid);
+        }
+        let Some(credential) = credentials.get(username).await? else {
+            return Ok(Login::Invalid);
+        };
 
-        let Some(hash) = logins_file.get(username).cloned() else {
-            return Ok(None);
+        let now = chrono::Utc::now();
+        if credential.totp.as_ref().is_some_and(Totp::is_enabled) {
+            let Some(code) = code else {
+                return Ok(Login::CodeRequired);
+            };
+            let is_valid = credentials
+                .update(username, |credential| {
+                    let is_valid = credential
+                        .totp
+                        .as_mut()
+                        .is_some_and(|totp| totp.use_any_code(&code, now));
+                    credential.record_attempt(is_valid, now);
+                    is_valid
+                })
+                .await?;
+            if is_valid != Some(true) {
+                return Ok(Login::Invalid);
+            }
+        } else if credential.failed_attempts > 0 {
+            credentials
+                .update(username, |credential| credential.record_attempt(true, now))
+                .await?;
+        }
+
+        Ok(Login::Valid(Auth(())))
+    }
+
+    /// Checks only the password, for when the user already has a session but
+    /// should prove it's really them. Wrong passwords count towards being
+    /// locked out like they do when logging in.
+    pub async fn check_password(
+        credentials: &Credentials,
+        username: &str,
+        password: String,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(credential) = credentials.get(username).await? else {
+            return Ok(false);
         };
+        if credential.is_locked_out() {
+            return Ok(false);
+        }
 
+        let hash = credential.hash;
         let password_is_valid =
             tokio::task::spawn_blocking(move || verify_password(&password, &hash))
                 .await
                 .expect("task should not panic")?;
 
-        if password_is_valid {
-            Ok(Some(Auth(())))
-        } else {
-            Ok(None)
+        if !password_is_valid {
+            let now = chrono::Utc::now();
+            credentials
+                .update(username, |credential| credential.record_attempt(false, now))
+                .await?;
         }
+        Ok(password_is_valid)
     }
 
     /// `Ok(Some(Auth))` if created, `Ok(None)` if the user id already exists, `Err` if
-    /// logins.txt could not be read/argon2 hashing failed
+    /// credentials could not be read/argon2 hashing failed
     pub async fn write_entry(
+        credentials: &Credentials,
         username: &str,
         password: String,
     ) -> Result<Option<Auth>, Box<dyn std::error::Error>> {
-        let logins_file = read_logins().await?;
-        if logins_file.contains_key(username) {
+        // saves hashing when it's obviously taken, but only the insert is certain
+        if credentials.get(username).await?.is_some() {
             return Ok(None);
         }
 
@@ -81,13 +172,395 @@ impl Auth {
             .await
             .expect("task should not panic")?;
 
-        tokio::fs::OpenOptions::new()
-            .append(true)
-            .open(std::path::Path::new(STORE_PATH).join("logins.txt"))
+        let now = chrono::Utc::now();
+        let credential = Credential {
+            hash,
+            created_at: now,
+            updated_at: now,
+            failed_attempts: 0,
+            last_failed_at: None,
+            totp: None,
+        };
+        if !credentials.insert(username, credential).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(Auth(())))
+    }
+
+    /// Replaces the password of a login. Whoever calls this is responsible for
+    /// checking the user is allowed to.
+    pub async fn set_password(
+        credentials: &Credentials,
+        username: &str,
+        password: String,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let hash = tokio::task::spawn_blocking(move || hash_password(&password))
+            .await
+            .expect("task should not panic")?;
+
+        let changed = credentials
+            .update(username, |credential| {
+                credential.hash = hash;
+                credential.updated_at = chrono::Utc::now();
+                credential.failed_attempts = 0;
+                credential.last_failed_at = None;
+            })
+            .await?;
+        if changed.is_none() {
+            return Err(format!("user {username} has no credential").into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Credential {
+    fn record_attempt(&mut self, is_valid: bool, now: chrono::DateTime<chrono::Utc>) {
+        if is_valid {
+            self.failed_attempts = 0;
+        } else {
+            self.failed_attempts += 1;
+            self.last_failed_at = Some(now);
+        }
+    }
+
+    fn is_locked_out(&self) -> bool {
+        let Some(last_failed_at) = self.last_failed_at else {
+            return false;
+        };
+
+        self.failed_attempts >= crate::blog::MAX_FAILED_LOGINS
+            && chrono::Utc::now() - last_failed_at
+                < chrono::Duration::from_std(crate::blog::FAILED_LOGIN_LOCKOUT)
+                    .expect("Constant std::duration be in range of chrono::duration")
+    }
+}
+
+impl Credentials {
+    pub fn new(store: Arc<dyn Store>) -> Credentials {
+        Credentials {
+            store,
+            cache: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get(&self, username: &str) -> std::io::Result<Option<Credential>> {
+        let mut cache = self.cache.lock().await;
+        Self::get_cached(&self.store, &mut cache, username).await
+    }
+
+    /// Adds the credential only if the user doesn't have one yet, returning
+    /// whether it did
+    pub async fn insert(&self, username: &str, credential: Credential) -> std::io::Result<bool> {
+        let mut cache = self.cache.lock().await;
+        if Self::get_cached(&self.store, &mut cache, username)
             .await?
-            .write_all(format!("\n{username}\t{hash}").as_bytes())
+            .is_some()
+        {
+            return Ok(false);
+        }
+
+        if !self.store.insert_credential(username, &credential).await? {
+            return Ok(false);
+        }
+        cache.insert(username.to_owned(), credential);
+        Ok(true)
+    }
+
+    /// Changes the user's credential and writes it back, returning `None` if
+    /// they don't have one
+    pub async fn update<T>(
+        &self,
+        username: &str,
+        update: impl FnOnce(&mut Credential) -> T,
+    ) -> std::io::Result<Option<T>> {
+        let mut cache = self.cache.lock().await;
+        let Some(mut credential) = Self::get_cached(&self.store, &mut cache, username).await?
+        else {
+            return Ok(None);
+        };
+
+        let result = update(&mut credential);
+        self.store.put_credential(username, &credential).await?;
+        cache.insert(username.to_owned(), credential);
+        Ok(Some(result))
+    }
+
+    /// Starts enrolling an authenticator app, replacing one that was never
+    /// confirmed. `None` if the user already has one.
+    pub async fn begin_totp(&self, username: &str) -> std::io::Result<Option<Totp>> {
+        let totp = self
+            .update(username, |credential| {
+                if credential.totp.as_ref().is_some_and(Totp::is_enabled) {
+                    return None;
+                }
+                let totp = Totp::generate();
+                credential.totp = Some(totp.clone());
+                Some(totp)
+            })
             .await?;
+        Ok(totp.flatten())
+    }
 
-        Ok(Some(Auth(())))
+    /// Finishes enrolling once the app shows the right code, returning the new
+    /// recovery codes. `None` if the code is wrong or nothing is being enrolled.
+    pub async fn confirm_totp(
+        &self,
+        username: &str,
+        code: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> std::io::Result<Option<Vec<String>>> {
+        let recovery_codes = self
+            .update(username, |credential| {
+                let totp = credential.totp.as_mut().filter(|totp| !totp.is_enabled())?;
+                if !totp.use_code(code, now) {
+                    return None;
+                }
+
+                let recovery_codes = (0..crate::blog::RECOVERY_CODE_COUNT)
+                    .map(|_| {
+                        crate::blog::get_random_hex_string::<{ crate::blog::RECOVERY_CODE_BYTES }>()
+                    })
+                    .collect::<Vec<_>>();
+                totp.recovery_codes = recovery_codes
+                    .iter()
+                    .map(|code| hash_recovery_code(code))
+                    .collect();
+                totp.confirmed_at = Some(now);
+                Some(recovery_codes)
+            })
+            .await?;
+        Ok(recovery_codes.flatten())
+    }
+
+    /// Removes the user's authenticator app if `code` is from it or is one of
+    /// their recovery codes. Wrong codes count towards being locked out.
+    pub async fn disable_totp(
+        &self,
+        username: &str,
+        code: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> std::io::Result<bool> {
+        let is_disabled = self
+            .update(username, |credential| {
+                if credential.is_locked_out() {
+                    return false;
+                }
+                let is_valid = credential
+                    .totp
+                    .as_mut()
+                    .is_some_and(|totp| totp.is_enabled() && totp.use_any_code(code, now));
+
+                credential.record_attempt(is_valid, now);
+                if is_valid {
+                    credential.totp = None;
+                }
+                is_valid
+            })
+            .await?;
+        Ok(is_disabled == Some(true))
+    }
+
+    async fn get_cached(
+        store: &Arc<dyn Store>,
+        cache: &mut HashMap<String, Credential>,
+        username: &str,
+    ) -> std::io::Result<Option<Credential>> {
+        if let Some(credential) = cache.get(username) {
+            return Ok(Some(credential.clone()));
+        }
+
+        let credential = store.get_credential(username).await?;
+        if let Some(credential) = &credential {
+            cache.insert(username.to_owned(), credential.clone());
+        }
+        Ok(credential)
+    }
+}
+
+impl Totp {
+    fn generate() -> Totp {
+        let mut secret = [0u8; crate::blog::TOTP_SECRET_BYTES];
+        rand_chacha::ChaCha20Rng::from_entropy().fill_bytes(&mut secret);
+
+        Totp {
+            secret: data_encoding::BASE32_NOPAD.encode(&secret),
+            confirmed_at: None,
+            last_used_step: None,
+            recovery_codes: Vec::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+
+    /// The `otpauth://` URI authenticator apps read, usually from a QR code
+    pub fn uri(&self, username: &str) -> String {
+        let issuer = urlencoding::encode(crate::blog::TOTP_ISSUER);
+        format!(
+            "otpauth://totp/{issuer}:{}?secret={}&issuer={issuer}&algorithm=SHA1&digits={}&period={}",
+            urlencoding::encode(username),
+            self.secret,
+            crate::blog::TOTP_DIGITS,
+            crate::blog::TOTP_STEP.as_secs(),
+        )
+    }
+
+    /// Checks a code from the app against the time steps around `now`, using
+    /// it up if it's right
+    pub fn use_code(&mut self, code: &str, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let code = code.trim();
+        if code.len() != crate::blog::TOTP_DIGITS as usize
+            || !code.bytes().all(|b| b.is_ascii_digit())
+        {
+            return false;
+        }
+        let Ok(code) = code.parse::<u32>() else {
+            return false;
+        };
+        let Ok(secret) = data_encoding::BASE32_NOPAD.decode(self.secret.as_bytes()) else {
+            return false;
+        };
+
+        let step = totp_step(now);
+        let steps =
+            step.saturating_sub(crate::blog::TOTP_SKEW_STEPS)..=step + crate::blog::TOTP_SKEW_STEPS;
+        for step in steps {
+            if self
+                .last_used_step
+                .is_some_and(|last_used| step <= last_used)
+            {
+                continue;
+            }
+            if totp_code(&secret, step) == code {
+                self.last_used_step = Some(step);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Checks a recovery code, using it up if it's right
+    pub fn use_recovery_code(&mut self, code: &str) -> bool {
+        let hash = hash_recovery_code(code);
+        let Some(index) = self.recovery_codes.iter().position(|it| *it == hash) else {
+            return false;
+        };
+
+        self.recovery_codes.remove(index);
+        true
+    }
+
+    /// Either a code from the app or a recovery code
+    pub fn use_any_code(&mut self, code: &str, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.use_code(code, now) || self.use_recovery_code(code)
+    }
+}
+
+/// Which RFC 6238 time step `now` is in
+pub fn totp_step(now: chrono::DateTime<chrono::Utc>) -> u64 {
+    now.timestamp().max(0) as u64 / crate::blog::TOTP_STEP.as_secs()
+}
+
+/// The RFC 4226 HOTP value for a time step, which is what the app shows
+pub fn totp_code(secret: &[u8], step: u64) -> u32 {
+    let mut mac = hmac::Hmac::<sha1::Sha1>::new_from_slice(secret)
+        .expect("HMAC should accept keys of any length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let truncated = u32::from_be_bytes(
+        hash[offset..offset + 4]
+            .try_into()
+            .expect("offset should leave 4 bytes"),
+    ) & 0x7fff_ffff;
+
+    truncated % 10u32.pow(crate::blog::TOTP_DIGITS)
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    crate::blog::to_hex_string(&sha2::Sha256::digest(
+        code.trim().to_ascii_lowercase().as_bytes(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 secret from RFC 6238's test vectors
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    fn at(timestamp: i64) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp(timestamp, 0).unwrap()
+    }
+
+    fn totp() -> Totp {
+        Totp {
+            secret: data_encoding::BASE32_NOPAD.encode(RFC_SECRET),
+            confirmed_at: Some(at(0)),
+            last_used_step: None,
+            recovery_codes: vec![hash_recovery_code("abcde-fghij")],
+        }
+    }
+
+    fn code_at(timestamp: i64) -> String {
+        format!("{:06}", totp_code(RFC_SECRET, totp_step(at(timestamp))))
+    }
+
+    #[test]
+    fn codes_match_rfc_6238() {
+        // the RFC gives 8 digits, of which these are the last 6
+        for (timestamp, code) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(totp_code(RFC_SECRET, totp_step(at(timestamp))), code);
+        }
+    }
+
+    #[test]
+    fn codes_are_accepted_one_step_either_side() {
+        let now = 1234567890;
+        let step = crate::blog::TOTP_STEP.as_secs() as i64;
+
+        for offset in [-1, 0, 1] {
+            let code = code_at(now + offset * step);
+            assert!(totp().use_code(&code, at(now)), "{offset} steps away");
+        }
+        for offset in [-2, 2] {
+            let code = code_at(now + offset * step);
+            assert!(!totp().use_code(&code, at(now)), "{offset} steps away");
+        }
+    }
+
+    #[test]
+    fn codes_can_only_be_used_once() {
+        let now = 1234567890;
+        let step = crate::blog::TOTP_STEP.as_secs() as i64;
+        let mut totp = totp();
+
+        assert!(totp.use_code(&code_at(now), at(now)));
+        assert!(!totp.use_code(&code_at(now), at(now)));
+        // nor can an older one that's still within the window
+        assert!(!totp.use_code(&code_at(now - step), at(now)));
+        assert!(totp.use_code(&code_at(now + step), at(now + step)));
+    }
+
+    #[test]
+    fn recovery_codes_can_only_be_used_once() {
+        let mut totp = totp();
+
+        assert!(!totp.use_recovery_code("wrong-code"));
+        assert!(totp.use_any_code(" ABCDE-FGHIJ ", at(0)));
+        assert!(!totp.use_recovery_code("abcde-fghij"));
+        assert!(totp.recovery_codes.is_empty());
     }
 }
@@ -1,11 +1,16 @@
 use rand::{RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 
 pub type PostID = String;
 pub type SessionID = String;
+pub type RefreshToken = String;
+pub type ApiToken = String;
 pub type InviteID = String;
+pub type CircleID = String;
+pub type UploadID = String;
+pub type PasswordResetID = String;
 
 #[cfg(not(debug_assertions))]
 pub const STORE_PATH: &str = "/home/shared/frith-store/blog";
@@ -14,13 +19,44 @@ pub const STORE_PATH: &str = "test-store";
 
 pub const POST_ID_BYTES: usize = 16;
 pub const SESSION_ID_BYTES: usize = 32;
+pub const REFRESH_TOKEN_BYTES: usize = 32;
+pub const API_TOKEN_BYTES: usize = 32;
 pub const INVITE_ID_BYTES: usize = 32;
+pub const CIRCLE_ID_BYTES: usize = 8;
+pub const UPLOAD_ID_BYTES: usize = 16;
+pub const PASSWORD_RESET_ID_BYTES: usize = 32;
+/// 160 bits, as RFC 4226 recommends for HMAC-SHA1
+pub const TOTP_SECRET_BYTES: usize = 20;
+pub const RECOVERY_CODE_BYTES: usize = 5;
+pub const RECOVERY_CODE_COUNT: usize = 10;
 
+/// how long a session lasts without being used
 pub const SESSION_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60 * 24);
+/// how long a session lasts however much it's used, before it has to be refreshed
+pub const SESSION_MAX_LIFETIME: std::time::Duration =
+    std::time::Duration::from_secs(60 * 60 * 24 * 7);
+pub const REFRESH_TOKEN_TTL: std::time::Duration =
+    std::time::Duration::from_secs(60 * 60 * 24 * 30);
 pub const INCOMPLETE_POST_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
 pub const INVITE_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60 * 24 * 7);
+pub const PASSWORD_RESET_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60 * 24);
+pub const SESSION_LAST_USE_PRECISION: std::time::Duration = std::time::Duration::from_secs(60 * 5);
+pub const SESSION_CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+/// wrong passwords in a row before logging in is refused for a while
+pub const MAX_FAILED_LOGINS: u32 = 10;
+pub const FAILED_LOGIN_LOCKOUT: std::time::Duration = std::time::Duration::from_secs(60 * 15);
+/// shown as the account's provider in authenticator apps
+pub const TOTP_ISSUER: &str = "blog.frith.gay";
+pub const TOTP_STEP: std::time::Duration = std::time::Duration::from_secs(30);
+pub const TOTP_DIGITS: u32 = 6;
+/// how many steps either side of now a code is still accepted for, to allow
+/// for clock drift
+pub const TOTP_SKEW_STEPS: u64 = 1;
+/// the most posts `latest` gives back at once
+pub const MAX_LATEST_POSTS: usize = 50;
+pub const PROFILE_UPLOAD_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 5);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub username: String,
     pub name: String,
@@ -29,6 +65,59 @@ pub struct User {
 
     pub permissions: Permissions,
     pub members: HashSet<String>,
+    /// named subsets of `members` that posts can be shared with
+    #[serde(default)]
+    pub circles: HashMap<CircleID, Circle>,
+    /// keyed by the username asking to become a member
+    #[serde(default)]
+    pub member_requests: HashMap<String, MemberRequest>,
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+/// The parts of a user's page they fill in themselves
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Markdown
+    pub bio: String,
+    pub links: Vec<ProfileLink>,
+    /// file names under the user's media, once uploaded
+    pub avatar: Option<String>,
+    pub header: Option<String>,
+}
+
+/// The pictures on a user's profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileImage {
+    Avatar,
+    Header,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileLink {
+    pub label: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberRequest {
+    pub requested_at: chrono::DateTime<chrono::Utc>,
+    pub status: MemberRequestStatus,
+}
+
+/// Approved requests are removed, since the requester is then a member
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberRequestStatus {
+    Pending,
+    Denied,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Circle {
+    pub name: String,
+    pub members: HashSet<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,14 +129,47 @@ pub struct Post {
     pub replies: Vec<PostID>,
     pub quotes: Vec<PostID>,
     pub in_progress: bool,
+    // meta files written before visibility levels only have `is_private`
+    #[serde(default, alias = "is_private")]
+    pub visibility: Visibility,
+    /// the author's circles a members-only post is shared with, or all of
+    /// their members when empty
+    #[serde(default)]
+    pub audience: Vec<CircleID>,
+    /// when the text was last changed after publishing
     #[serde(default)]
-    pub is_private: bool,
+    pub edited_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A previous version of a post's text, kept when the post is edited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    /// counting from 1 for the text the post was published with
+    pub number: u32,
+    pub written_at: chrono::DateTime<chrono::Utc>,
+    pub text: String,
+}
+
+/// Who can see a post
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, enum_iterator::Sequence)]
+pub enum Visibility {
+    #[default]
+    Public,
+    /// anyone with the link, but left out of `latest`
+    Unlisted,
+    /// the author and their members
+    Members,
+    /// only the author
+    Author,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Permissions {
     pub can_create_invites: bool,
     pub can_create_posts: bool,
+    /// lets the user mint password resets for other accounts
+    #[serde(default)]
+    pub can_reset_passwords: bool,
 }
 
 // #[derive(Debug)]
@@ -57,6 +179,10 @@ pub fn get_random_hex_string<const LEN: usize>() -> String {
     let mut bytes = [0u8; LEN];
     rand_chacha::ChaCha20Rng::from_entropy().fill_bytes(&mut bytes);
 
+    to_hex_string(&bytes)
+}
+
+pub fn to_hex_string(bytes: &[u8]) -> String {
     bytes.iter().fold(String::new(), |mut output, b| {
         let _ = write!(output, "{b:02x}");
         output
@@ -68,3 +194,114 @@ impl Post {
         self.reply_to.is_some()
     }
 }
+
+impl Profile {
+    pub fn image(&self, image: ProfileImage) -> Option<&String> {
+        match image {
+            ProfileImage::Avatar => self.avatar.as_ref(),
+            ProfileImage::Header => self.header.as_ref(),
+        }
+    }
+    pub fn image_mut(&mut self, image: ProfileImage) -> &mut Option<String> {
+        match image {
+            ProfileImage::Avatar => &mut self.avatar,
+            ProfileImage::Header => &mut self.header,
+        }
+    }
+}
+
+impl ProfileImage {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProfileImage::Avatar => "avatar",
+            ProfileImage::Header => "header",
+        }
+    }
+}
+
+impl User {
+    pub fn has_circles(&self, circle_ids: &[CircleID]) -> bool {
+        circle_ids
+            .iter()
+            .all(|circle_id| self.circles.contains_key(circle_id))
+    }
+
+    /// Whether `username` is in the post's audience, assuming the post is
+    /// members-only and this is its author
+    pub fn is_in_audience(&self, post: &Post, username: &str) -> bool {
+        if !self.members.contains(username) {
+            return false;
+        }
+        post.audience.is_empty()
+            || post.audience.iter().any(|circle_id| {
+                self.circles
+                    .get(circle_id)
+                    .is_some_and(|circle| circle.members.contains(username))
+            })
+    }
+
+    /// Removes someone from the members and every circle
+    pub fn remove_member(&mut self, username: &str) {
+        self.members.remove(username);
+        for circle in self.circles.values_mut() {
+            circle.members.remove(username);
+        }
+    }
+}
+
+impl Visibility {
+    pub fn name(self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Members => "members",
+            Visibility::Author => "author",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Visibility> {
+        enum_iterator::all::<Visibility>().find(|visibility| visibility.name() == name)
+    }
+
+    /// Whether the post shows up in lists like `latest`, as opposed to only
+    /// being reachable by its id
+    pub fn is_listed(self) -> bool {
+        self != Visibility::Unlisted
+    }
+}
+
+impl Serialize for Visibility {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// Also accepts the old `is_private` bool, where private meant members only
+impl<'de> Deserialize<'de> for Visibility {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VisibilityVisitor;
+
+        impl serde::de::Visitor<'_> for VisibilityVisitor {
+            type Value = Visibility;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a visibility name or an `is_private` bool")
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, is_private: bool) -> Result<Visibility, E> {
+                Ok(if is_private {
+                    Visibility::Members
+                } else {
+                    Visibility::Public
+                })
+            }
+
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<Visibility, E> {
+                Visibility::from_name(name).ok_or_else(|| {
+                    E::unknown_variant(name, &["public", "unlisted", "members", "author"])
+                })
+            }
+        }
+
+        deserializer.deserialize_any(VisibilityVisitor)
+    }
+}
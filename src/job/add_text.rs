@@ -0,0 +1,23 @@
+use super::{Job, JobResult, PostJob};
+use crate::state::incomplete::IncompletePost;
+use crate::state::State;
+
+pub const JOB: PostJob = PostJob(&AddText);
+
+/// Add the actual content of a post. This is finished by the client sending
+/// the text, so running it only checks that the text was written.
+struct AddText;
+
+#[async_trait::async_trait]
+impl Job for AddText {
+    fn name(&self) -> &'static str {
+        "AddText"
+    }
+
+    async fn run(&self, state: &State, post: &IncompletePost) -> JobResult {
+        match state.store.get_text(&post.meta.id).await? {
+            Some(_) => Ok(()),
+            None => Err(format!("post {} has no text", post.meta.id).into()),
+        }
+    }
+}
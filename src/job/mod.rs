@@ -1,12 +1,115 @@
+pub mod add_text;
+pub mod queue;
 pub mod reply;
 pub mod thumbnails;
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, enum_iterator::Sequence)]
-pub enum PostJob {
-    /// Add the actual content of a post
-    AddText,
-    /// Create thumbnails for all of a post's images
-    Thumbnails,
-    /// Update the parent's `replies` entry
-    ReplyParent,
+use crate::state::incomplete::IncompletePost;
+use crate::state::State;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+/// Jobs that fail are retried, so they should be safe to run more than once
+pub type JobResult = Result<(), JobError>;
+
+/// Every job a post can have. New jobs get a module under `job/` and an entry
+/// here; the order is the one they're listed in a post's status.
+const JOBS: &[PostJob] = &[add_text::JOB, thumbnails::JOB, reply::JOB];
+
+/// A step in processing a post before it's published
+#[async_trait::async_trait]
+pub trait Job: Send + Sync {
+    /// Identifies the job in drafts and the queue, so it shouldn't change
+    fn name(&self) -> &'static str;
+
+    /// Jobs that have to finish before this one is queued
+    fn dependencies(&self) -> &'static [PostJob] {
+        &[]
+    }
+
+    /// Jobs that can't safely be run again after failing partway are given up
+    /// on after the first failure
+    fn is_idempotent(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, state: &State, post: &IncompletePost) -> JobResult;
+}
+
+/// A handle to one of the registered jobs, stored by name
+#[derive(Clone, Copy)]
+pub struct PostJob(&'static dyn Job);
+
+impl PostJob {
+    pub fn all() -> impl Iterator<Item = PostJob> {
+        JOBS.iter().copied()
+    }
+
+    pub fn from_name(name: &str) -> Option<PostJob> {
+        PostJob::all().find(|job| job.name() == name)
+    }
+
+    /// Whether none of the job's dependencies are left
+    pub fn is_ready(self, jobs_left: &HashSet<PostJob>) -> bool {
+        !self
+            .dependencies()
+            .iter()
+            .any(|dependency| jobs_left.contains(dependency))
+    }
+}
+
+/// Runs blocking work, like decoding images, off the async workers. A panic
+/// fails the job instead of taking down the worker.
+pub async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, JobError> + Send + 'static,
+) -> Result<T, JobError> {
+    tokio::task::spawn_blocking(work).await?
+}
+
+impl std::ops::Deref for PostJob {
+    type Target = dyn Job;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl PartialEq for PostJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+
+impl Eq for PostJob {}
+
+impl std::hash::Hash for PostJob {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name().hash(state);
+    }
+}
+
+impl std::fmt::Debug for PostJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::fmt::Display for PostJob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for PostJob {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for PostJob {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        PostJob::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown post job {name}")))
+    }
 }
@@ -0,0 +1,245 @@
+use super::PostJob;
+use crate::blog::PostID;
+use crate::state::State;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type QueuedJobID = String;
+
+pub const JOB_WORKERS: usize = 4;
+pub const MAX_JOB_ATTEMPTS: u32 = 6;
+const JOB_RETRY_BASE_DELAY: Duration = Duration::from_secs(10);
+const JOB_RETRY_MAX_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// A post job waiting to be run, as written to the store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub id: QueuedJobID,
+    pub post_id: PostID,
+    pub job: PostJob,
+    /// how many times the job has failed
+    pub attempts: u32,
+    pub run_after: chrono::DateTime<chrono::Utc>,
+    pub last_error: Option<String>,
+}
+
+/// The jobs that haven't run yet. The store holds the durable copy; this is
+/// what the workers pick from.
+#[derive(Debug, Default)]
+pub struct JobQueue {
+    jobs: tokio::sync::Mutex<Jobs>,
+    notify: tokio::sync::Notify,
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    pending: Vec<QueuedJob>,
+    /// taken by a worker and not finished yet, so they aren't queued twice
+    running: HashSet<QueuedJobID>,
+}
+
+impl QueuedJob {
+    pub fn new(post_id: PostID, job: PostJob) -> QueuedJob {
+        QueuedJob {
+            // a post can only have one of each job, so requeueing one is a no-op
+            id: format!("{post_id}-{job}"),
+            post_id,
+            job,
+            attempts: 0,
+            run_after: chrono::Utc::now(),
+            last_error: None,
+        }
+    }
+
+    fn retry_delay(&self) -> Duration {
+        JOB_RETRY_BASE_DELAY
+            .saturating_mul(2u32.saturating_pow(self.attempts.saturating_sub(1)))
+            .min(JOB_RETRY_MAX_DELAY)
+    }
+}
+
+impl JobQueue {
+    /// Whether the job is waiting to run or running
+    async fn contains(&self, job_id: &QueuedJobID) -> bool {
+        let jobs = self.jobs.lock().await;
+        jobs.running.contains(job_id)
+            || jobs
+                .pending
+                .iter()
+                .any(|pending_job| pending_job.id == *job_id)
+    }
+
+    async fn push(&self, job: QueuedJob) {
+        self.jobs.lock().await.pending.push(job);
+        self.notify.notify_one();
+    }
+
+    /// Called once a job taken with [`JobQueue::next`] has run, whether or not
+    /// it was requeued
+    async fn finish(&self, job_id: &QueuedJobID) {
+        self.jobs.lock().await.running.remove(job_id);
+    }
+
+    async fn next(&self) -> QueuedJob {
+        loop {
+            let next_run_after = {
+                let mut jobs = self.jobs.lock().await;
+                let now = chrono::Utc::now();
+
+                if let Some(index) = jobs.pending.iter().position(|job| job.run_after <= now) {
+                    let job = jobs.pending.swap_remove(index);
+                    jobs.running.insert(job.id.clone());
+                    return job;
+                }
+                jobs.pending.iter().map(|job| job.run_after).min()
+            };
+
+            if let Some(next_run_after) = next_run_after {
+                let wait = (next_run_after - chrono::Utc::now())
+                    .to_std()
+                    .unwrap_or(Duration::ZERO);
+                _ = tokio::time::timeout(wait, self.notify.notified()).await;
+            } else {
+                self.notify.notified().await;
+            }
+        }
+    }
+}
+
+impl State {
+    /// Queues the jobs left over from the last run. Should be called before
+    /// anything else is queued.
+    pub async fn load_jobs(&self) -> std::io::Result<()> {
+        for job in self.store.list_jobs().await? {
+            self.jobs.push(job).await;
+        }
+        Ok(())
+    }
+
+    pub fn spawn_job_workers(self: &Arc<Self>) {
+        for _ in 0..JOB_WORKERS {
+            let state = self.clone();
+            tokio::spawn(async move {
+                loop {
+                    let job = state.jobs.next().await;
+                    let job_id = job.id.clone();
+                    state.run_queued_job(job).await;
+                    state.jobs.finish(&job_id).await;
+                }
+            });
+        }
+    }
+
+    /// Does nothing if the job is already waiting to run or running
+    pub async fn enqueue_job(&self, job: QueuedJob) -> std::io::Result<()> {
+        if self.jobs.contains(&job.id).await {
+            return Ok(());
+        }
+
+        self.store.put_job(&job).await?;
+        self.jobs.push(job).await;
+        Ok(())
+    }
+
+    async fn run_queued_job(&self, job: QueuedJob) {
+        let post = match self.store.get_incomplete(&job.post_id).await {
+            Ok(Some(it)) => it,
+            Ok(None) => {
+                // the post was deleted or already published
+                self.delete_queued_job(&job).await;
+                return;
+            }
+            Err(err) => {
+                eprintln!(
+                    "Error reading draft state for job {} of post {}: {err}",
+                    job.id, job.post_id
+                );
+                self.retry_job(job, err.to_string()).await;
+                return;
+            }
+        };
+
+        // already done, or queued again when its dependencies finish
+        if !post.jobs_left.contains(&job.job) || !job.job.is_ready(&post.jobs_left) {
+            self.delete_queued_job(&job).await;
+            return;
+        }
+
+        let result = job.job.run(self, &post).await;
+
+        match result {
+            Ok(()) => {
+                self.delete_queued_job(&job).await;
+                self.finish_post_job(&job.post_id, job.job).await;
+            }
+            Err(err) => {
+                eprintln!(
+                    "Error running job {} (attempt {}): {err}",
+                    job.id,
+                    job.attempts + 1
+                );
+                self.retry_job(job, err.to_string()).await;
+            }
+        }
+    }
+
+    async fn retry_job(&self, mut job: QueuedJob, error: String) {
+        job.attempts += 1;
+        job.last_error = Some(error);
+
+        if job.attempts >= MAX_JOB_ATTEMPTS || !job.job.is_idempotent() {
+            eprintln!(
+                "Job {} failed {} times, moving it to the dead letter list",
+                job.id, job.attempts
+            );
+            match self.store.put_dead_job(&job).await {
+                Ok(()) => (),
+                Err(err) => eprintln!("Error writing dead job {}: {err}", job.id),
+            }
+            self.delete_queued_job(&job).await;
+            // a post shouldn't be stuck in progress forever because of a thumbnail
+            self.finish_post_job(&job.post_id, job.job).await;
+            return;
+        }
+
+        job.run_after = chrono::Utc::now()
+            + chrono::Duration::from_std(job.retry_delay())
+                .expect("retry delay should be in range of chrono::duration");
+        match self.store.put_job(&job).await {
+            Ok(()) => (),
+            Err(err) => eprintln!("Error writing retried job {}: {err}", job.id),
+        }
+        self.jobs.push(job).await;
+    }
+
+    async fn delete_queued_job(&self, job: &QueuedJob) {
+        match self.store.delete_job(&job.id).await {
+            Ok(()) => (),
+            Err(err) => eprintln!("Error deleting finished job {}: {err}", job.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn running_jobs_are_not_queued_again() {
+        let state = crate::state::tests::state();
+        let job = QueuedJob::new("post".to_owned(), crate::job::thumbnails::JOB);
+
+        state.enqueue_job(job.clone()).await.unwrap();
+        let running = state.jobs.next().await;
+        assert_eq!(running.id, job.id);
+
+        state.enqueue_job(job.clone()).await.unwrap();
+        assert!(state.jobs.jobs.lock().await.pending.is_empty());
+
+        state.jobs.finish(&job.id).await;
+        state.enqueue_job(job.clone()).await.unwrap();
+        assert_eq!(state.jobs.jobs.lock().await.pending.len(), 1);
+    }
+}
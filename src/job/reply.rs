@@ -1,38 +1,64 @@
-use crate::blog::Post;
+use super::{Job, JobResult, PostJob};
+use crate::state::incomplete::IncompletePost;
+use crate::state::State;
 
-pub async fn run(post: &Post) {
+pub const JOB: PostJob = PostJob(&ReplyParent);
+
+/// Update the parent's `replies` entry
+struct ReplyParent;
+
+#[async_trait::async_trait]
+impl Job for ReplyParent {
+    fn name(&self) -> &'static str {
+        "ReplyParent"
+    }
+
+    fn dependencies(&self) -> &'static [PostJob] {
+        // a reply shouldn't be linked before it has any content
+        &[super::add_text::JOB]
+    }
+
+    async fn run(&self, state: &State, post: &IncompletePost) -> JobResult {
+        link_to_parent(state, &post.meta).await
+    }
+}
+
+async fn link_to_parent(state: &State, post: &crate::blog::Post) -> JobResult {
     let Some(parent_id) = post.reply_to.as_ref() else {
-        return;
+        return Ok(());
     };
 
-    let parent_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("post")
-        .join(parent_id)
-        .join("meta.json");
-
-    let parent_meta = match tokio::fs::read(&parent_path).await {
-        Ok(it) => it,
+    let _parent_lock = state.lock_post(parent_id).await;
+    let mut parent_meta = match state.store.get_post(parent_id).await {
+        Ok(Some(it)) => it,
+        Ok(None) => {
+            // the parent was deleted, there's nothing to link
+            eprintln!("Parent post {parent_id} of post {} does not exist", post.id);
+            return Ok(());
+        }
         Err(err) => {
             eprintln!(
                 "Error reading file for parent post {parent_id} of post {}: {err}",
                 post.id
             );
-            return;
+            return Err(err.into());
         }
     };
 
-    let mut parent_meta =
-        serde_json::from_slice::<Post>(&parent_meta).expect("stored post should deserialize");
+    // a retry after a failed write may find the reply already linked
+    if parent_meta.replies.contains(&post.id) {
+        return Ok(());
+    }
     parent_meta.replies.push(post.id.clone());
 
-    let parent_meta = serde_json::to_vec(&parent_meta).expect("post should serialize");
-    match tokio::fs::write(&parent_path, &parent_meta).await {
-        Ok(()) => (),
+    match state.store.put_post(&parent_meta).await {
+        Ok(()) => Ok(()),
         Err(err) => {
             eprintln!(
                 "Error writing file for parent post {parent_id} of post {}: {err}",
                 post.id
             );
+            Err(err.into())
         }
     }
 }
@@ -1,98 +1,162 @@
+use super::{Job, JobResult, PostJob};
+use crate::state::incomplete::IncompletePost;
+use crate::state::State;
+use crate::store::MediaSize;
+
 const SMALL_THUMB_SIZE: u32 = 128;
 const LARGE_THUMB_SIZE: u32 = 512;
 
-pub fn run(post: &crate::state::incomplete::IncompletePost) {
-    let image_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("post")
-        .join(&post.meta.id)
-        .join("image");
+pub const JOB: PostJob = PostJob(&Thumbnails);
 
-    for image_name in &post.media.images {
-        let raw_path = image_path.join("raw").join(image_name);
-        let small_path = image_path.join("small").join(image_name);
-        let large_path = image_path.join("large").join(image_name);
+/// Create thumbnails for all of a post's images
+struct Thumbnails;
 
-        let small_thumb = match create_thumb(&raw_path, SMALL_THUMB_SIZE) {
-            Ok(it) => it,
-            Err(err) => {
+#[async_trait::async_trait]
+impl Job for Thumbnails {
+    fn name(&self) -> &'static str {
+        "Thumbnails"
+    }
+
+    fn dependencies(&self) -> &'static [PostJob] {
+        // images can still be added until the text is sent
+        &[super::add_text::JOB]
+    }
+
+    async fn run(&self, state: &State, post: &IncompletePost) -> JobResult {
+        create_thumbs(state.store.as_ref(), post).await
+    }
+}
+
+/// Fails if any thumbnail couldn't be made, after trying all of them. Images
+/// whose upload never went through are skipped.
+async fn create_thumbs(store: &dyn crate::store::Store, post: &IncompletePost) -> JobResult {
+    let mut failed_thumbs = 0;
+
+    for image_name in &post.media.images {
+        let raw = match store
+            .get_media(&post.meta.id, MediaSize::Raw, image_name)
+            .await
+        {
+            Ok(Some(it)) => it,
+            Ok(None) => {
                 eprintln!(
-                    "Error creating small thumbnail for image {image_name} for post {}: {err}",
+                    "Raw image {image_name} for post {} does not exist",
                     post.meta.id
                 );
                 continue;
             }
-        };
-        let large_thumb = match create_thumb(&raw_path, LARGE_THUMB_SIZE) {
-            Ok(it) => it,
             Err(err) => {
                 eprintln!(
-                    "Error creating large thumbnail for image {image_name} for post {}: {err}",
+                    "Error reading raw image {image_name} for post {}: {err}",
                     post.meta.id
                 );
+                failed_thumbs += 2;
                 continue;
             }
         };
 
-        if let Some(small_thumb) = small_thumb {
-            match small_thumb.save(&small_path) {
-                Ok(()) => (),
-                Err(err) => {
-                    eprintln!(
-                        "Error writing small thumbnail for image {image_name} for post {}: {err}",
-                        post.meta.id
-                    );
-                }
-            }
-        } else {
-            match std::fs::copy(&raw_path, &small_path) {
-                Ok(_) => (),
+        for (size, max_size) in [
+            (MediaSize::Small, SMALL_THUMB_SIZE),
+            (MediaSize::Large, LARGE_THUMB_SIZE),
+        ] {
+            let thumb = {
+                let image_name = image_name.clone();
+                let raw = raw.clone();
+                super::run_blocking(move || create_thumb(&raw, &image_name, max_size)).await
+            };
+
+            let thumb = match thumb {
+                Ok(it) => it,
                 Err(err) => {
                     eprintln!(
-                        "Error writing small copy for image {image_name} for post {}: {err}",
+                        "Error creating {} thumbnail for image {image_name} for post {}: {err}",
+                        size.folder_name(),
                         post.meta.id
                     );
+                    failed_thumbs += 1;
+                    continue;
                 }
-            }
-        }
-        if let Some(large_thumb) = large_thumb {
-            match large_thumb.save(&large_path) {
+            };
+
+            // animated images are copied as-is
+            let data = thumb.as_deref().unwrap_or(&raw);
+            match store.put_media(&post.meta.id, size, image_name, data).await {
                 Ok(()) => (),
                 Err(err) => {
                     eprintln!(
-                        "Error writing large thumbnail for image {image_name} for post {}: {err}",
-                        post.meta.id
-                    );
-                }
-            }
-        } else {
-            match std::fs::copy(&raw_path, &large_path) {
-                Ok(_) => (),
-                Err(err) => {
-                    eprintln!(
-                        "Error writing large copy for image {image_name} for post {}: {err}",
+                        "Error writing {} thumbnail for image {image_name} for post {}: {err}",
+                        size.folder_name(),
                         post.meta.id
                     );
+                    failed_thumbs += 1;
                 }
             }
         }
     }
+
+    if failed_thumbs > 0 {
+        return Err(format!(
+            "{failed_thumbs} of {} thumbnails failed",
+            post.media.images.len() * 2
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Both thumbnails of an image that isn't part of a post, like a user's avatar
+pub async fn create_thumbs_of(
+    raw: &[u8],
+    image_name: &str,
+) -> Result<[(MediaSize, Vec<u8>); 2], super::JobError> {
+    let mut thumbs = [
+        (MediaSize::Small, Vec::new()),
+        (MediaSize::Large, Vec::new()),
+    ];
+
+    for (size, thumb) in &mut thumbs {
+        let max_size = match size {
+            MediaSize::Small => SMALL_THUMB_SIZE,
+            _ => LARGE_THUMB_SIZE,
+        };
+        let created = {
+            let (raw, image_name) = (raw.to_vec(), image_name.to_owned());
+            super::run_blocking(move || create_thumb(&raw, &image_name, max_size)).await?
+        };
+        // animated images are copied as-is
+        *thumb = created.unwrap_or_else(|| raw.to_vec());
+    }
+
+    Ok(thumbs)
 }
 
 // TODO: better result type
 fn create_thumb(
-    image_path: &std::path::Path,
+    raw: &[u8],
+    image_name: &str,
     max_size: u32,
-) -> Result<Option<image::DynamicImage>, Box<dyn std::error::Error>> {
-    let image = image::io::Reader::open(image_path)?;
+) -> Result<Option<Vec<u8>>, super::JobError> {
+    let image = image::io::Reader::new(std::io::Cursor::new(raw));
     let image = image.with_guessed_format()?;
+    let Some(format) = image.format() else {
+        return Err("unrecognized image format".into());
+    };
 
     // TODO: animated formats
-    if let image::ImageFormat::Gif = image.format().expect("image should have format") {
+    if let image::ImageFormat::Gif = format {
         return Ok(None);
     }
 
     let image = image.decode()?;
-    Ok(Some(create_thumb_static(image, max_size)))
+    let thumb = create_thumb_static(image, max_size);
+
+    // keep the format the file extension promises, since that's what the
+    // content type is guessed from when serving
+    let output_format = image::ImageFormat::from_path(image_name).unwrap_or(format);
+    let mut thumb_data = std::io::Cursor::new(Vec::new());
+    thumb.write_to(&mut thumb_data, output_format)?;
+
+    Ok(Some(thumb_data.into_inner()))
 }
 
 fn create_thumb_static(image: image::DynamicImage, max_size: u32) -> image::DynamicImage {
@@ -6,19 +6,35 @@ mod blog;
 mod job;
 mod routes;
 mod state;
+mod store;
 
 #[tokio::main]
 async fn main() {
-    let state = std::sync::Arc::new(state::State::new());
-    restore_incomplete_posts(state.clone())
-        .await
-        .expect("error reprocessing in-progress posts");
+    let state = std::sync::Arc::new(state::State::new(
+        store::from_env().await.expect("error opening store"),
+    ));
+    if let Err(err) = state.load_sessions().await {
+        eprintln!("Error loading sessions: {err}");
+    }
+    if let Err(err) = state.load_tokens().await {
+        eprintln!("Error loading API tokens: {err}");
+    }
+    if let Err(err) = state.load_jobs().await {
+        eprintln!("Error loading queued jobs: {err}");
+    }
+    if let Err(err) = restore_incomplete_posts(state.clone()).await {
+        eprintln!("Error reprocessing in-progress posts: {err}");
+    }
+    state.spawn_job_workers();
+    state.spawn_session_cleanup();
 
     let cors = CorsLayer::new()
         .allow_origin(tower_http::cors::AllowOrigin::exact(
             axum::http::HeaderValue::from_static("https://frith.gay"),
         ))
-        .allow_headers(tower_http::cors::Any);
+        // so the session cookie is sent, which rules out allowing any header
+        .allow_credentials(true)
+        .allow_headers(tower_http::cors::AllowHeaders::mirror_request());
 
     let app = axum::Router::new()
         .nest("/api", routes::api::route())
@@ -38,55 +54,73 @@ async fn main() {
             return;
         }
     };
-    axum::serve(listener, app.into_make_service())
-        .await
-        .expect("Error serving app");
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .expect("Error serving app");
 }
 
 async fn restore_incomplete_posts(
     state: std::sync::Arc<crate::state::State>,
 ) -> std::io::Result<()> {
     async fn try_restore_post(
-        path: std::path::PathBuf,
+        post_id: crate::blog::PostID,
         state: std::sync::Arc<crate::state::State>,
-    ) -> std::io::Result<Option<crate::blog::PostID>> {
-        let meta = tokio::fs::read(path.join("meta.json")).await?;
-        let meta = serde_json::from_slice::<crate::blog::Post>(&meta)
-            .expect("post metadata should deserialize");
+    ) -> Option<crate::blog::PostID> {
+        let meta = match state.store.get_post(&post_id).await {
+            Ok(Some(it)) => it,
+            Ok(None) => return None,
+            Err(err) => {
+                eprintln!("Error reading meta for post {post_id}, skipping it: {err}");
+                return None;
+            }
+        };
 
         if !meta.in_progress {
-            return Ok(None);
+            return None;
         }
 
-        let post_id = meta.id.clone();
-        state.posts_in_progress.write().await.insert(
-            post_id.clone(),
-            state::incomplete::IncompletePost {
-                jobs_left: enum_iterator::all().collect(),
+        let incomplete_post = match state.store.get_incomplete(&post_id).await {
+            Ok(Some(it)) => it,
+            // posts started before drafts were persisted
+            Ok(None) => state::incomplete::IncompletePost {
+                jobs_left: crate::job::PostJob::all().collect(),
                 meta,
                 media: state::incomplete::Media::default(),
             },
-        );
+            Err(err) => {
+                eprintln!("Error reading draft state for post {post_id}, skipping it: {err}");
+                return None;
+            }
+        };
 
-        Ok(Some(post_id))
+        if incomplete_post
+            .jobs_left
+            .contains(&crate::job::add_text::JOB)
+        {
+            state
+                .posts_in_progress
+                .write()
+                .await
+                .insert(post_id.clone(), incomplete_post);
+        } else {
+            // the post was already finished, so make sure its jobs are queued
+            state.complete_post(incomplete_post).await;
+        }
+
+        Some(post_id)
     }
 
-    let mut posts_dir =
-        tokio::fs::read_dir(std::path::Path::new(crate::blog::STORE_PATH).join("post")).await?;
     let mut process_set = tokio::task::JoinSet::new();
 
-    while let Some(entry) = posts_dir.next_entry().await? {
-        process_set.spawn(try_restore_post(entry.path(), state.clone()));
+    for post_id in state.store.list_posts().await? {
+        process_set.spawn(try_restore_post(post_id, state.clone()));
     }
 
-    while let Some(maybe_restored_post_id) = process_set
-        .join_next()
-        .await
-        .transpose()
-        .expect("task should not panic")
-        .transpose()?
-    {
-        let Some(restored_post_id) = maybe_restored_post_id else {
+    while let Some(maybe_restored_post_id) = process_set.join_next().await {
+        let Some(restored_post_id) = maybe_restored_post_id.expect("task should not panic") else {
             continue;
         };
 
@@ -97,3 +131,154 @@ async fn restore_incomplete_posts(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in};
+    use crate::store::MediaSize;
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn png() -> Vec<u8> {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(4, 4)
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        png.into_inner()
+    }
+
+    /// What `main` does on startup, minus serving
+    async fn restart(store: Arc<dyn crate::store::Store>) -> Arc<crate::state::State> {
+        let state = Arc::new(crate::state::State::new(store));
+        state.load_sessions().await.unwrap();
+        state.load_jobs().await.unwrap();
+        super::restore_incomplete_posts(state.clone())
+            .await
+            .unwrap();
+        state.spawn_job_workers();
+        state
+    }
+
+    async fn wait_until_published(state: &crate::state::State, post_id: &String) {
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            while state
+                .store
+                .get_post(post_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .in_progress
+            {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the post should be published");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn drafts_survive_a_restart() {
+        let store: Arc<dyn crate::store::Store> =
+            Arc::new(crate::store::memory::MemoryStore::default());
+        let state = Arc::new(crate::state::State::new(store.clone()));
+
+        let mut author = add_user(&state, "author").await;
+        let parent = crate::store::tests::post("parent", "author");
+        state.store.put_post(&parent).await.unwrap();
+        author.posts.push(parent.id.clone());
+        state.store.put_user(&author).await.unwrap();
+        let session = log_in(&state, "author").await;
+
+        let start = || async {
+            let (status, post_id) = request(
+                &state,
+                Method::POST,
+                "/api/post/create/start",
+                Some(&session),
+                Some(json!({ "reply_to": parent.id })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+            String::from_utf8(post_id.to_vec()).unwrap()
+        };
+
+        // one draft still waiting on its text, with an uploaded image
+        let draft_id = start().await;
+        let (status, _) = request(
+            &state,
+            Method::POST,
+            &format!("/api/post/create/image/{draft_id}"),
+            Some(&session),
+            Some(json!({ "name": "picture.png" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        state
+            .store
+            .append_media(&draft_id, MediaSize::Raw, "picture.png", &png())
+            .await
+            .unwrap();
+        state.end_upload(&draft_id, "picture.png", true).await;
+
+        // and one whose text was sent, but whose jobs never got to run
+        let finished_id = start().await;
+        let (status, _) = request(
+            &state,
+            Method::POST,
+            "/api/post/create/finish",
+            Some(&session),
+            Some(json!({ "post_id": finished_id, "text": "finished" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        drop(state);
+
+        let state = restart(store).await;
+
+        {
+            let posts_in_progress = state.posts_in_progress.read().await;
+            let draft = &posts_in_progress[&draft_id];
+            assert_eq!(
+                draft.jobs_left,
+                [
+                    crate::job::add_text::JOB,
+                    crate::job::reply::JOB,
+                    crate::job::thumbnails::JOB
+                ]
+                .into_iter()
+                .collect()
+            );
+            assert_eq!(draft.media.images, vec!["picture.png".to_owned()]);
+            assert!(draft.media.uploads["picture.png"].finished);
+            assert!(!posts_in_progress.contains_key(&finished_id));
+        }
+
+        let (status, _) = request(
+            &state,
+            Method::POST,
+            "/api/post/create/finish",
+            Some(&session),
+            Some(json!({ "post_id": draft_id, "text": "draft" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        wait_until_published(&state, &draft_id).await;
+        wait_until_published(&state, &finished_id).await;
+
+        assert!(state
+            .store
+            .get_media(&draft_id, MediaSize::Small, "picture.png")
+            .await
+            .unwrap()
+            .is_some());
+        let parent = state.store.get_post(&parent.id).await.unwrap().unwrap();
+        assert!(parent.replies.contains(&draft_id));
+        assert!(parent.replies.contains(&finished_id));
+        let author = state.store.get_user("author").await.unwrap().unwrap();
+        assert!(author.posts.contains(&draft_id));
+        assert!(author.posts.contains(&finished_id));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::blog::SessionID;
+use crate::state::session::NewSession;
+use crate::state::token::Scope;
+use crate::state::State;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// The cookie browsers get their session in, so scripts on the page can't
+/// read it
+const SESSION_COOKIE: &str = "session";
+
+/// A logged in user, from an `Authorization: Bearer <session>` header or the
+/// session cookie. Requests without a usable session are rejected with a 401.
+///
+/// Only sessions from logging in are accepted, unless `S` is one of the
+/// [`scope`]s, in which case API tokens with that scope are too.
+#[derive(Debug)]
+pub(super) struct AuthenticatedUser<S = SessionOnly> {
+    pub username: String,
+    /// the session or API token the request was made with
+    pub session_id: SessionID,
+    scope: PhantomData<S>,
+}
+
+pub(super) trait RequiredScope {
+    /// `None` if API tokens aren't accepted at all
+    const SCOPE: Option<Scope>;
+}
+
+/// For managing the account itself, which API tokens can't do
+#[derive(Debug)]
+pub(super) struct SessionOnly;
+
+impl RequiredScope for SessionOnly {
+    const SCOPE: Option<Scope> = None;
+}
+
+/// Which [`Scope`] an API token needs to be accepted
+pub(super) mod scope {
+    use super::{RequiredScope, Scope};
+
+    #[derive(Debug)]
+    pub struct ReadPrivate;
+    #[derive(Debug)]
+    pub struct CreatePost;
+    #[derive(Debug)]
+    pub struct DeletePost;
+    #[derive(Debug)]
+    pub struct ManageMembers;
+
+    impl RequiredScope for ReadPrivate {
+        const SCOPE: Option<Scope> = Some(Scope::ReadPrivate);
+    }
+    impl RequiredScope for CreatePost {
+        const SCOPE: Option<Scope> = Some(Scope::CreatePost);
+    }
+    impl RequiredScope for DeletePost {
+        const SCOPE: Option<Scope> = Some(Scope::DeletePost);
+    }
+    impl RequiredScope for ManageMembers {
+        const SCOPE: Option<Scope> = Some(Scope::ManageMembers);
+    }
+}
+
+#[axum::async_trait]
+impl<S: RequiredScope> FromRequestParts<Arc<State>> for AuthenticatedUser<S> {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<State>,
+    ) -> Result<Self, Self::Rejection> {
+        let session_id = request_session(&parts.headers).ok_or(StatusCode::UNAUTHORIZED)?;
+
+        let username = match S::SCOPE {
+            Some(scope) => state.authorize(&session_id, scope).await,
+            None => state
+                .get_session(&session_id)
+                .await
+                .map(|session| session.for_username),
+        };
+
+        Ok(AuthenticatedUser {
+            username: username.ok_or(StatusCode::UNAUTHORIZED)?,
+            session_id,
+            scope: PhantomData,
+        })
+    }
+}
+
+/// The session from an `Authorization: Bearer <session>` header, or else from
+/// the session cookie
+pub(super) fn request_session(headers: &HeaderMap) -> Option<SessionID> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    if let Some(session_id) = bearer {
+        return Some(session_id.to_owned());
+    }
+
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then(|| value.to_owned())
+        })
+}
+
+/// Sends a new session back in the body, for clients using the
+/// `Authorization` header, and as an HttpOnly cookie, for browsers
+pub(super) fn session_response(new_session: NewSession) -> Response {
+    let cookie = session_cookie(
+        &new_session.session,
+        crate::blog::SESSION_MAX_LIFETIME.as_secs(),
+    );
+    ([(header::SET_COOKIE, cookie)], Json(new_session)).into_response()
+}
+
+/// Makes the browser forget its session cookie
+pub(super) fn clear_session_cookie() -> [(header::HeaderName, String); 1] {
+    [(header::SET_COOKIE, session_cookie("", 0))]
+}
+
+fn session_cookie(value: &str, max_age: u64) -> String {
+    format!(
+        "{SESSION_COOKIE}={value}; Max-Age={max_age}; Path=/api; HttpOnly; Secure; SameSite=Strict"
+    )
+}
@@ -1,4 +1,5 @@
-use crate::blog::{InviteID, Permissions, SessionID};
+use crate::blog::{InviteID, Permissions};
+use crate::routes::api::authenticated::AuthenticatedUser;
 use crate::state::SharedState;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -7,24 +8,23 @@ use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub(super) struct InviteOptions {
-    session: SessionID,
     for_permissions: Permissions,
 }
 
 pub(super) async fn post(
     State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
     Json(request): Json<InviteOptions>,
 ) -> Result<InviteID, StatusCode> {
-    let Some(session) = state.get_session(&request.session).await else {
-        return Err(StatusCode::UNAUTHORIZED);
-    };
-
-    let user = super::user::get(axum::extract::Path(session.for_username))
-        .await?
-        .0;
+    let user = super::user::read(&state, &username).await?;
     if !user.permissions.can_create_invites {
         return Err(StatusCode::FORBIDDEN);
     }
+    // otherwise anyone who can invite could make themselves a second account
+    // that can reset everyone's passwords
+    if request.for_permissions.can_reset_passwords && !user.permissions.can_reset_passwords {
+        return Err(StatusCode::FORBIDDEN);
+    }
 
     Ok(state.create_invite(request.for_permissions).await)
 }
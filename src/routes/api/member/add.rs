@@ -1,4 +1,4 @@
-use crate::blog::SessionID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
 use crate::state::SharedState;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -7,41 +7,62 @@ use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub(super) struct MemberAddOptions {
-    session: SessionID,
     for_username: String,
 }
 
 pub(super) async fn put(
     State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
     Json(request): Json<MemberAddOptions>,
 ) -> StatusCode {
-    let Some(session) = state.get_session(&request.session).await else {
-        return StatusCode::UNAUTHORIZED;
-    };
-
-    let mut user = match crate::routes::api::user::get(axum::extract::Path(
-        session.for_username.clone(),
-    ))
-    .await
-    {
-        Ok(Json(user)) => user,
-        Err(err) => return err,
-    };
-
-    user.members.insert(request.for_username.clone());
-
-    match tokio::fs::write(
-        std::path::Path::new(crate::blog::STORE_PATH)
-            .join("user")
-            .join(format!("{}.json", session.for_username)),
-        serde_json::to_vec(&user).expect("user should serialize"),
-    )
-    .await
-    {
+    if let Err(err) = super::check_new_member(&state, &username, &request.for_username).await {
+        return err;
+    }
+
+    let result = super::update_user(&state, &username, |user| {
+        user.member_requests.remove(&request.for_username);
+        user.members.insert(request.for_username);
+        Ok(())
+    })
+    .await;
+
+    match result {
         Ok(()) => StatusCode::OK,
-        Err(err) => {
-            eprintln!("Error writing user {}.json: {err}", session.for_username);
-            StatusCode::INTERNAL_SERVER_ERROR
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn only_other_existing_users_can_be_added() {
+        let state = state();
+        add_user(&state, "author").await;
+        add_user(&state, "reader").await;
+        let session = log_in(&state, "author").await;
+
+        for (for_username, expected) in [
+            ("nobody", StatusCode::NOT_FOUND),
+            ("author", StatusCode::BAD_REQUEST),
+            ("reader", StatusCode::OK),
+        ] {
+            let (status, _) = request(
+                &state,
+                Method::PUT,
+                "/api/member/add",
+                Some(&session),
+                Some(json!({ "for_username": for_username })),
+            )
+            .await;
+            assert_eq!(status, expected, "adding {for_username}");
         }
+
+        let author = state.store.get_user("author").await.unwrap().unwrap();
+        assert_eq!(author.members, ["reader".to_owned()].into_iter().collect());
     }
 }
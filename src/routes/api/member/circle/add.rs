@@ -0,0 +1,85 @@
+use crate::blog::CircleID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CircleAddOptions {
+    for_username: String,
+}
+
+/// Also makes them a member if they weren't already
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Path(circle_id): Path<CircleID>,
+    Json(request): Json<CircleAddOptions>,
+) -> StatusCode {
+    if let Err(err) = super::check_new_member(&state, &username, &request.for_username).await {
+        return err;
+    }
+
+    let result = super::update_user(&state, &username, |user| {
+        let circle = user
+            .circles
+            .get_mut(&circle_id)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        circle.members.insert(request.for_username.clone());
+        user.members.insert(request.for_username);
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn only_other_existing_users_can_be_added() {
+        let state = state();
+        let mut author = add_user(&state, "author").await;
+        author.circles.insert(
+            "circle".to_owned(),
+            crate::blog::Circle {
+                name: "friends".to_owned(),
+                members: Default::default(),
+            },
+        );
+        state.store.put_user(&author).await.unwrap();
+        add_user(&state, "reader").await;
+        let session = log_in(&state, "author").await;
+
+        for (for_username, expected) in [
+            ("nobody", StatusCode::NOT_FOUND),
+            ("author", StatusCode::BAD_REQUEST),
+            ("reader", StatusCode::OK),
+        ] {
+            let (status, _) = request(
+                &state,
+                Method::PUT,
+                "/api/member/circle/circle/add",
+                Some(&session),
+                Some(json!({ "for_username": for_username })),
+            )
+            .await;
+            assert_eq!(status, expected, "adding {for_username}");
+        }
+
+        let author = state.store.get_user("author").await.unwrap().unwrap();
+        let reader = ["reader".to_owned()].into_iter().collect();
+        assert_eq!(author.members, reader);
+        assert_eq!(author.circles["circle"].members, reader);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::blog::{Circle, CircleID};
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CircleCreateOptions {
+    name: String,
+}
+
+pub(super) async fn post(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Json(request): Json<CircleCreateOptions>,
+) -> Result<(StatusCode, CircleID), StatusCode> {
+    if request.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let circle_id = crate::blog::get_random_hex_string::<{ crate::blog::CIRCLE_ID_BYTES }>();
+    super::update_user(&state, &username, |user| {
+        user.circles.insert(
+            circle_id.clone(),
+            Circle {
+                name: request.name,
+                members: HashSet::new(),
+            },
+        );
+        Ok(())
+    })
+    .await?;
+
+    Ok((StatusCode::CREATED, circle_id))
+}
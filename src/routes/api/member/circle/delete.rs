@@ -0,0 +1,26 @@
+use crate::blog::CircleID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+
+/// Posts shared with the circle stay limited to whichever of their other
+/// circles are left, which may be none
+pub(super) async fn delete(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Path(circle_id): Path<CircleID>,
+) -> StatusCode {
+    let result = super::update_user(&state, &username, |user| {
+        user.circles
+            .remove(&circle_id)
+            .map(|_| ())
+            .ok_or(StatusCode::NOT_FOUND)
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
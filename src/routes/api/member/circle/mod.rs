@@ -0,0 +1,17 @@
+use super::{check_new_member, update_user};
+use crate::state::NestedRouter;
+use axum::routing::{post, put};
+
+mod add;
+mod create;
+mod delete;
+mod remove;
+mod rename;
+
+pub fn route() -> NestedRouter {
+    axum::Router::new()
+        .route("/", post(create::post))
+        .route("/:id", put(rename::put).delete(delete::delete))
+        .route("/:id/add", put(add::put))
+        .route("/:id/remove", put(remove::put))
+}
@@ -0,0 +1,35 @@
+use crate::blog::CircleID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CircleRemoveOptions {
+    for_username: String,
+}
+
+/// They stay a member; `/api/member/revoke` removes them from everything
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Path(circle_id): Path<CircleID>,
+    Json(request): Json<CircleRemoveOptions>,
+) -> StatusCode {
+    let result = super::update_user(&state, &username, |user| {
+        let circle = user
+            .circles
+            .get_mut(&circle_id)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        circle.members.remove(&request.for_username);
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
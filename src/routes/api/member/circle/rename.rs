@@ -0,0 +1,38 @@
+use crate::blog::CircleID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct CircleRenameOptions {
+    name: String,
+}
+
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Path(circle_id): Path<CircleID>,
+    Json(request): Json<CircleRenameOptions>,
+) -> StatusCode {
+    if request.name.trim().is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let result = super::update_user(&state, &username, |user| {
+        let circle = user
+            .circles
+            .get_mut(&circle_id)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        circle.name = request.name;
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
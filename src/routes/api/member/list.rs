@@ -0,0 +1,62 @@
+use crate::blog::{Circle, CircleID};
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub(super) struct Members {
+    members: Vec<String>,
+    circles: HashMap<CircleID, Circle>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct MemberOf {
+    username: String,
+    name: String,
+}
+
+/// The viewer's own members and circles
+pub(super) async fn members(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ReadPrivate>,
+) -> Result<Json<Members>, StatusCode> {
+    let user = crate::routes::api::user::read(&state, &username).await?;
+    let mut members = user.members.into_iter().collect::<Vec<_>>();
+    members.sort();
+
+    Ok(Json(Members {
+        members,
+        circles: user.circles,
+    }))
+}
+
+/// The authors the viewer is a member of. Which of their circles the viewer is
+/// in stays private to the author.
+pub(super) async fn member_of(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ReadPrivate>,
+) -> Result<Json<Vec<MemberOf>>, StatusCode> {
+    let users = match state.store.list_users().await {
+        Ok(it) => it,
+        Err(err) => {
+            eprintln!("Error reading users: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut member_of = users
+        .into_iter()
+        .filter(|user| user.members.contains(&username))
+        .map(|user| MemberOf {
+            username: user.username,
+            name: user.name,
+        })
+        .collect::<Vec<_>>();
+    member_of.sort_by(|a, b| a.username.cmp(&b.username));
+
+    Ok(Json(member_of))
+}
@@ -1,13 +1,35 @@
+use crate::routes::api::user::update as update_user;
 use crate::state::NestedRouter;
-use axum::routing::put;
+use axum::routing::{get, put};
 
 mod add;
+mod circle;
 mod leave;
+mod list;
+mod request;
 mod revoke;
 
 pub fn route() -> NestedRouter {
     axum::Router::new()
+        .route("/", get(list::members))
+        .route("/of", get(list::member_of))
         .route("/add", put(add::put))
         .route("/revoke", put(revoke::put))
         .route("/leave", put(leave::put))
+        .nest("/circle", circle::route())
+        .nest("/request", request::route())
+}
+
+/// Members have to be an existing user other than the one adding them
+async fn check_new_member(
+    state: &crate::state::State,
+    username: &str,
+    for_username: &str,
+) -> Result<(), axum::http::StatusCode> {
+    if username == for_username {
+        return Err(axum::http::StatusCode::BAD_REQUEST);
+    }
+    crate::routes::api::user::read(state, for_username)
+        .await
+        .map(|_| ())
 }
@@ -0,0 +1,31 @@
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct MemberApproveOptions {
+    for_username: String,
+}
+
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Json(request): Json<MemberApproveOptions>,
+) -> StatusCode {
+    let result = super::update_user(&state, &username, |user| {
+        user.member_requests
+            .remove(&request.for_username)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        user.members.insert(request.for_username);
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
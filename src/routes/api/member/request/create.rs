@@ -0,0 +1,44 @@
+use crate::blog::{MemberRequest, MemberRequestStatus};
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct MemberRequestOptions {
+    for_username: String,
+}
+
+/// Asks `for_username` to be made one of their members. A denied request
+/// can't be made again.
+pub(super) async fn post(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Json(request): Json<MemberRequestOptions>,
+) -> StatusCode {
+    if username == request.for_username {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let result = super::update_user(&state, &request.for_username, |user| {
+        if user.members.contains(&username) || user.member_requests.contains_key(&username) {
+            return Err(StatusCode::CONFLICT);
+        }
+        user.member_requests.insert(
+            username,
+            MemberRequest {
+                requested_at: chrono::Utc::now(),
+                status: MemberRequestStatus::Pending,
+            },
+        );
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::CREATED,
+        Err(err) => err,
+    }
+}
@@ -0,0 +1,34 @@
+use crate::blog::MemberRequestStatus;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct MemberDenyOptions {
+    for_username: String,
+}
+
+/// The request is kept so the requester can see it was denied
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ManageMembers>,
+    Json(request): Json<MemberDenyOptions>,
+) -> StatusCode {
+    let result = super::update_user(&state, &username, |user| {
+        let member_request = user
+            .member_requests
+            .get_mut(&request.for_username)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        member_request.status = MemberRequestStatus::Denied;
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
@@ -0,0 +1,53 @@
+use crate::blog::{MemberRequest, MemberRequestStatus};
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub(super) struct OutgoingRequest {
+    for_username: String,
+    requested_at: chrono::DateTime<chrono::Utc>,
+    status: MemberRequestStatus,
+}
+
+/// Requests from others to become the viewer's members, keyed by username
+pub(super) async fn incoming(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ReadPrivate>,
+) -> Result<Json<HashMap<String, MemberRequest>>, StatusCode> {
+    let user = crate::routes::api::user::read(&state, &username).await?;
+    Ok(Json(user.member_requests))
+}
+
+/// The viewer's own requests that haven't been approved yet
+pub(super) async fn outgoing(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::ReadPrivate>,
+) -> Result<Json<Vec<OutgoingRequest>>, StatusCode> {
+    // requests are only stored on the user they're for
+    let users = match state.store.list_users().await {
+        Ok(it) => it,
+        Err(err) => {
+            eprintln!("Error reading users: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    Ok(Json(
+        users
+            .into_iter()
+            .filter_map(|user| {
+                let member_request = user.member_requests.get(&username)?;
+                Some(OutgoingRequest {
+                    requested_at: member_request.requested_at,
+                    status: member_request.status,
+                    for_username: user.username,
+                })
+            })
+            .collect(),
+    ))
+}
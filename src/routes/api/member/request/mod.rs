@@ -0,0 +1,17 @@
+use super::update_user;
+use crate::state::NestedRouter;
+use axum::routing::{get, post, put};
+
+mod approve;
+mod create;
+mod deny;
+mod list;
+
+pub fn route() -> NestedRouter {
+    axum::Router::new()
+        .route("/", post(create::post))
+        .route("/incoming", get(list::incoming))
+        .route("/outgoing", get(list::outgoing))
+        .route("/approve", put(approve::put))
+        .route("/deny", put(deny::put))
+}
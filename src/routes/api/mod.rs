@@ -1,19 +1,66 @@
 use crate::state::NestedRouter;
-use axum::routing::{get, post};
+use axum::routing::post;
 
+mod authenticated;
 mod invite;
 mod member;
 mod post;
 mod session;
 mod signup;
+mod token;
+mod upload;
 mod user;
+mod viewer;
 
 pub fn route() -> NestedRouter {
     axum::Router::new()
         .nest("/post", post::route())
         .nest("/member", member::route())
-        .route("/user/:id", get(user::get))
-        .route("/session", post(session::post))
+        .nest("/user", user::route())
+        .nest("/session", session::route())
+        .nest("/token", token::route())
         .route("/invite", post(invite::post))
         .route("/signup", post(signup::post))
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::blog::SessionID;
+    use crate::state::State;
+    use axum::http::{header, Method, Request, StatusCode};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// Sends one request through the whole API, the way `main` serves it
+    pub async fn request(
+        state: &Arc<State>,
+        method: Method,
+        uri: &str,
+        session: Option<&SessionID>,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, axum::body::Bytes) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(session) = session {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {session}"));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body.to_string())),
+            None => request.body(axum::body::Body::empty()),
+        }
+        .unwrap();
+
+        let response = axum::Router::new()
+            .nest("/api", super::route())
+            .with_state(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, body)
+    }
+}
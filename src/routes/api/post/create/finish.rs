@@ -1,4 +1,5 @@
-use crate::blog::{PostID, SessionID};
+use crate::blog::PostID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
 use crate::state::SharedState;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -7,58 +8,232 @@ use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub(super) struct PostFinishOptions {
-    session: SessionID,
     post_id: PostID,
     text: String,
 }
 
 pub(super) async fn post(
     State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::CreatePost>,
     Json(request): Json<PostFinishOptions>,
 ) -> StatusCode {
-    let Some(session) = state.get_session(&request.session).await else {
-        return StatusCode::UNAUTHORIZED;
-    };
-
-    let Some(mut post) = state
-        .posts_in_progress
-        .write()
-        .await
-        .remove(&request.post_id)
-    else {
-        return StatusCode::NOT_FOUND;
-    };
-
-    if !post.meta.in_progress {
-        // sanity check
-        eprintln!("Completed post {} in in-progress post list!", post.meta.id);
-        return StatusCode::INTERNAL_SERVER_ERROR;
-    }
-    if post.meta.author_username != session.for_username {
-        return StatusCode::FORBIDDEN;
-    }
     if request.text.trim().is_empty() {
         return StatusCode::BAD_REQUEST;
     }
 
-    match tokio::fs::write(
-        std::path::Path::new(crate::blog::STORE_PATH)
-            .join("post")
-            .join(&post.meta.id)
-            .join("text.md"),
-        request.text,
-    )
-    .await
-    {
+    // only taken out once it's certain to be finished, so a bad request doesn't
+    // lose the draft
+    let mut post = {
+        let mut posts_in_progress = state.posts_in_progress.write().await;
+        let Some(post) = posts_in_progress.get(&request.post_id) else {
+            return StatusCode::NOT_FOUND;
+        };
+
+        if !post.meta.in_progress {
+            // sanity check
+            eprintln!("Completed post {} in in-progress post list!", post.meta.id);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        if post.meta.author_username != username {
+            return StatusCode::FORBIDDEN;
+        }
+
+        posts_in_progress
+            .remove(&request.post_id)
+            .expect("post should still be in progress while the lock is held")
+    };
+
+    match state.store.put_text(&post.meta.id, &request.text).await {
         Ok(()) => (),
         Err(err) => {
             eprintln!("Error writing text for post {}: {err}", request.post_id);
+            // so finishing can be tried again
+            state
+                .posts_in_progress
+                .write()
+                .await
+                .insert(post.meta.id.clone(), post);
             return StatusCode::INTERNAL_SERVER_ERROR;
         }
     }
 
-    post.jobs_left.remove(&crate::job::PostJob::AddText);
+    post.jobs_left.remove(&crate::job::add_text::JOB);
+    match state.store.put_incomplete(&post).await {
+        Ok(()) => (),
+        Err(err) => {
+            // the jobs will still run, but won't be resumed after a restart
+            eprintln!("Error writing draft state for post {}: {err}", post.meta.id);
+        }
+    }
+    state.send_post_event(
+        &post.meta.id,
+        crate::state::events::PostEventKind::JobFinished(crate::job::add_text::JOB),
+    );
     tokio::task::spawn(async move { state.complete_post(post).await });
 
     StatusCode::OK
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    async fn finish_many_posts_at_once(state: Arc<crate::state::State>) {
+        const POSTS: usize = 32;
+        state.spawn_job_workers();
+
+        let mut author = add_user(&state, "author").await;
+        let parent = crate::store::tests::post("parent", "author");
+        state.store.put_post(&parent).await.unwrap();
+        author.posts.push(parent.id.clone());
+        state.store.put_user(&author).await.unwrap();
+        let session = log_in(&state, "author").await;
+
+        let mut post_ids = Vec::new();
+        let mut reply_ids = Vec::new();
+        for index in 0..POSTS {
+            let is_reply = index % 2 == 0;
+            let body = if is_reply {
+                json!({ "reply_to": parent.id })
+            } else {
+                json!({})
+            };
+            let (status, post_id) = request(
+                &state,
+                Method::POST,
+                "/api/post/create/start",
+                Some(&session),
+                Some(body),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+
+            let post_id = String::from_utf8(post_id.to_vec()).unwrap();
+            if is_reply {
+                reply_ids.push(post_id.clone());
+            }
+            post_ids.push(post_id);
+        }
+
+        let mut finishes = tokio::task::JoinSet::new();
+        for post_id in &post_ids {
+            let state = state.clone();
+            let session = session.clone();
+            let body = json!({ "post_id": post_id, "text": format!("text of {post_id}") });
+            finishes.spawn(async move {
+                request(
+                    &state,
+                    Method::POST,
+                    "/api/post/create/finish",
+                    Some(&session),
+                    Some(body),
+                )
+                .await
+                .0
+            });
+        }
+        while let Some(status) = finishes.join_next().await {
+            assert_eq!(status.unwrap(), StatusCode::OK);
+        }
+
+        let (author, parent) = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            loop {
+                let author = state.store.get_user("author").await.unwrap().unwrap();
+                let parent = state.store.get_post(&parent.id).await.unwrap().unwrap();
+                if author.posts.len() > POSTS && parent.replies.len() >= reply_ids.len() {
+                    return (author, parent);
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("every post should be published");
+
+        // give any duplicate writes a chance to land before checking for them
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let author = state
+            .store
+            .get_user(&author.username)
+            .await
+            .unwrap()
+            .unwrap();
+        let parent = state.store.get_post(&parent.id).await.unwrap().unwrap();
+
+        assert_eq!(author.posts.len(), POSTS + 1);
+        for post_id in &post_ids {
+            assert_eq!(author.posts.iter().filter(|it| *it == post_id).count(), 1);
+        }
+        assert_eq!(parent.replies.len(), reply_ids.len());
+        for reply_id in &reply_ids {
+            assert_eq!(
+                parent.replies.iter().filter(|it| *it == reply_id).count(),
+                1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejected_finishes_keep_the_draft() {
+        let state = state();
+        add_user(&state, "author").await;
+        add_user(&state, "someone").await;
+        let author_session = log_in(&state, "author").await;
+        let other_session = log_in(&state, "someone").await;
+
+        let (status, post_id) = request(
+            &state,
+            Method::POST,
+            "/api/post/create/start",
+            Some(&author_session),
+            Some(json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let post_id = String::from_utf8(post_id.to_vec()).unwrap();
+
+        for (session, text, expected) in [
+            (&other_session, "text", StatusCode::FORBIDDEN),
+            (&author_session, " ", StatusCode::BAD_REQUEST),
+        ] {
+            let (status, _) = request(
+                &state,
+                Method::POST,
+                "/api/post/create/finish",
+                Some(session),
+                Some(json!({ "post_id": post_id, "text": text })),
+            )
+            .await;
+            assert_eq!(status, expected);
+            assert!(state.posts_in_progress.read().await.contains_key(&post_id));
+        }
+
+        let (status, _) = request(
+            &state,
+            Method::POST,
+            "/api/post/create/finish",
+            Some(&author_session),
+            Some(json!({ "post_id": post_id, "text": "text" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn finishing_many_posts_at_once_links_each_once() {
+        finish_many_posts_at_once(state()).await;
+    }
+
+    /// The file tree actually yields between reading and writing, so a missing
+    /// lock shows up as lost or doubled links
+    #[tokio::test(flavor = "multi_thread")]
+    async fn finishing_many_posts_at_once_links_each_once_on_disk() {
+        let temp_dir = crate::store::tests::TempDir::new();
+        std::fs::create_dir_all(temp_dir.0.join("user")).unwrap();
+        let store = Arc::new(crate::store::fs::FsStore::new(&temp_dir.0));
+        finish_many_posts_at_once(Arc::new(crate::state::State::new(store))).await;
+    }
+}
@@ -1,32 +1,26 @@
-use crate::blog::{PostID, SessionID};
+use crate::blog::PostID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::routes::api::upload::{receive_chunks, ChunkWriter};
 use crate::state::SharedState;
+use crate::store::MediaSize;
 use axum::extract::ws::WebSocket;
 use axum::extract::{Path, State, WebSocketUpgrade};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Deserialize;
-use std::os::unix::ffi::OsStrExt;
-use std::time::{Duration, Instant};
-use tokio::io::AsyncWriteExt;
-
-const IMAGE_SOCKET_TTL: Duration = Duration::from_secs(60);
-const IMAGE_SOCKET_MESSAGE_TTL: Duration = Duration::from_secs(3);
 
 #[derive(Debug, Deserialize)]
 pub(super) struct ImageUploadOptions {
-    session: SessionID,
     name: String,
 }
 
 pub(super) async fn post(
     State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::CreatePost>,
     Path(post_id): Path<PostID>,
     Json(request): Json<ImageUploadOptions>,
 ) -> Result<String, StatusCode> {
-    let Some(session) = state.get_session(&request.session).await else {
-        return Err(StatusCode::UNAUTHORIZED);
-    };
     let image_name = std::path::Path::new(&request.name);
 
     let mut posts_in_progress = state.posts_in_progress.write().await;
@@ -37,7 +31,7 @@ pub(super) async fn post(
     if !post.meta.in_progress {
         return Err(StatusCode::NOT_FOUND);
     }
-    if post.meta.author_username != session.for_username {
+    if post.meta.author_username != username {
         return Err(StatusCode::FORBIDDEN);
     }
 
@@ -49,195 +43,189 @@ pub(super) async fn post(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    if post.media.images.is_empty() {
-        let post_path = std::path::Path::new(crate::blog::STORE_PATH)
-            .join("post")
-            .join(&post_id);
-
-        match tokio::fs::create_dir(post_path.join("image")).await {
-            Ok(()) => (),
-            Err(err) => {
-                eprintln!("Error creating image folders for post {post_id}: {err}");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        }
-        match tokio::fs::create_dir(post_path.join("image").join("small")).await {
-            Ok(()) => (),
-            Err(err) => {
-                eprintln!("Error creating image folders for post {post_id}: {err}");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        }
-        match tokio::fs::create_dir(post_path.join("image").join("large")).await {
-            Ok(()) => (),
-            Err(err) => {
-                eprintln!("Error creating image folders for post {post_id}: {err}");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        }
-        match tokio::fs::create_dir(post_path.join("image").join("raw")).await {
-            Ok(()) => (),
-            Err(err) => {
-                eprintln!("Error creating image folders for post {post_id}: {err}");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
+    // theoretically to_string_lossy should never lose any data as filenames are
+    // ultimately given as strings anyway
+    let image_name = image_name.to_string_lossy().into_owned();
+    match state
+        .store
+        .put_media(&post_id, MediaSize::Raw, &image_name, &[])
+        .await
+    {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error creating image file {image_name} for post {post_id}: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
 
-    let image_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("post")
-        .join(&post_id)
-        .join("image")
-        .join("raw")
-        .join(image_name);
-    match tokio::fs::write(&image_path, Vec::new()).await {
+    post.media.images.push(image_name.clone());
+    post.media.uploads.insert(
+        image_name.clone(),
+        crate::state::incomplete::Upload::default(),
+    );
+    // writing to meta.json is unnecessary because of state::complete_post
+    post.jobs_left.insert(crate::job::thumbnails::JOB);
+
+    match state.store.put_incomplete(post).await {
         Ok(()) => (),
         Err(err) => {
-            eprintln!("Error creating image file {image_path:?}: {err}");
+            eprintln!("Error writing draft state for post {post_id}: {err}");
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
 
-    // theoretically to_string_lossy should never lose any data as filenames are
-    // ultimately given as strings anyway
-    post.media
-        .images
-        .push(image_name.to_string_lossy().into_owned());
-    // writing to meta.json is unnecessary because of state::complete_post
-    post.jobs_left.insert(crate::job::PostJob::Thumbnails);
-
-    Ok(format!(
-        "image:{}",
-        urlencoding::encode_binary(image_name.as_bytes())
-    ))
+    Ok(format!("image:{}", urlencoding::encode(&image_name)))
 }
 
 pub(super) async fn ws(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::CreatePost>,
     Path((post_id, image_name)): Path<(PostID, String)>,
     socket: WebSocketUpgrade,
 ) -> Response {
-    let post = match crate::routes::api::post::meta::get(Path(post_id.clone())).await {
-        Ok(it) => it,
-        Err(err) => return err.into_response(),
-    };
-    if !post.in_progress {
-        return StatusCode::NOT_FOUND.into_response();
+    if let Err(status) = check_upload(&state, &username, &post_id, &image_name).await {
+        return status.into_response();
     }
 
-    socket.on_upgrade(|socket| {
-        handle_image_socket(
-            socket,
-            std::path::Path::new(crate::blog::STORE_PATH)
-                .join("post")
-                .join(post_id)
-                .join("image")
-                .join("raw")
-                .join(image_name),
-        )
-    })
+    socket.on_upgrade(|socket| handle_image_socket(socket, state, post_id, image_name))
 }
 
-async fn handle_image_socket(mut socket: WebSocket, image_path: std::path::PathBuf) {
-    let mut image_file = match tokio::fs::OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(&image_path)
-        .await
-    {
-        Ok(it) => it,
-        Err(err) => {
-            eprintln!("Error opening image file {image_path:?}: {err}");
-            _ = tokio::time::timeout(Duration::from_secs(5), socket.close()).await;
-            return;
-        }
+/// Only the author can upload to their draft, and only to images it asked for
+/// that aren't done yet
+async fn check_upload(
+    state: &crate::state::State,
+    username: &str,
+    post_id: &PostID,
+    image_name: &str,
+) -> Result<(), StatusCode> {
+    let posts_in_progress = state.posts_in_progress.read().await;
+    let Some(post) = posts_in_progress.get(post_id) else {
+        return Err(StatusCode::NOT_FOUND);
     };
-    let mut total_recv_time = Duration::ZERO;
-
-    match socket
-        .send(axum::extract::ws::Message::Text("go ahead!".to_owned()))
-        .await
-    {
-        Ok(()) => (),
-        Err(err) => {
-            eprintln!("Error sending initial heartbeat: {err}");
-            return;
-        }
+    if !post.meta.in_progress {
+        return Err(StatusCode::NOT_FOUND);
     }
+    if post.meta.author_username != username {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    match post.media.uploads.get(image_name) {
+        None => Err(StatusCode::NOT_FOUND),
+        Some(upload) if upload.finished => Err(StatusCode::CONFLICT),
+        Some(_) => Ok(()),
+    }
+}
 
-    loop {
-        let recv_start = Instant::now();
-        let message_or_timeout =
-            tokio::time::timeout(IMAGE_SOCKET_MESSAGE_TTL, socket.recv()).await;
-        let Ok(message) = message_or_timeout else {
-            close_socket(socket, "message timeout").await;
-            break;
-        };
-
-        total_recv_time += recv_start.elapsed();
-        if total_recv_time >= IMAGE_SOCKET_TTL {
-            close_socket(socket, "transfer timeout").await;
-            break;
-        }
+/// Writes chunks to the raw image, keeping track of the progress
+struct ImageChunks<'a> {
+    state: &'a crate::state::State,
+    post_id: &'a PostID,
+    image_name: &'a str,
+}
 
-        let message = match message {
-            Some(Ok(it)) => it,
-            Some(Err(err)) => {
-                eprintln!("Error handling image socket for {image_path:?}: {err}");
-                close_socket(socket, "connection error").await;
-                break;
-            }
-            None => break,
-        };
-
-        // HACK: the client will wait for the server to write the chunk it sent
-        // before sending a new one. This is slow, and should be replaced by a
-        // chunk indexing system in the future.
-        match message {
-            axum::extract::ws::Message::Binary(data) => {
-                match image_file.write_all(&data).await {
-                    Ok(()) => (),
-                    Err(err) => {
-                        eprintln!("Error writing to image file {image_path:?}: {err}");
-                        close_socket(socket, "server error").await;
-                        break;
-                    }
-                }
-                match socket
-                    .send(axum::extract::ws::Message::Text(String::new()))
-                    .await
-                {
-                    Ok(()) => (),
-                    Err(err) => {
-                        eprintln!("Error sending heartbeat: {err}");
-                        return;
-                    }
-                }
-            }
-            axum::extract::ws::Message::Close(_) => return,
-            axum::extract::ws::Message::Ping(_)
-            | axum::extract::ws::Message::Pong(_)
-            | axum::extract::ws::Message::Text(_) => (),
-        }
+#[async_trait::async_trait]
+impl ChunkWriter for ImageChunks<'_> {
+    async fn write_chunk(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.state
+            .store
+            .append_media(self.post_id, MediaSize::Raw, self.image_name, data)
+            .await?;
+        self.state
+            .record_upload_progress(self.post_id, self.image_name, data.len())
+            .await;
+        Ok(())
     }
+}
 
-    drop(image_file);
-    match tokio::fs::remove_file(&image_path).await {
+async fn handle_image_socket(
+    socket: WebSocket,
+    state: std::sync::Arc<crate::state::State>,
+    post_id: PostID,
+    image_name: String,
+) {
+    let mut chunks = ImageChunks {
+        state: &state,
+        post_id: &post_id,
+        image_name: &image_name,
+    };
+    let description = format!("image {image_name} for post {post_id}");
+    if receive_chunks(socket, &description, &mut chunks).await {
+        state.end_upload(&post_id, &image_name, true).await;
+        return;
+    }
+
+    match state
+        .store
+        .delete_media(&post_id, MediaSize::Raw, &image_name)
+        .await
+    {
         Ok(()) => (),
-        Err(err) => eprintln!("Could not delete image file {image_path:?}: {err}"),
+        Err(err) => eprintln!("Could not delete image {image_name} for post {post_id}: {err}"),
     }
+    state.end_upload(&post_id, &image_name, false).await;
 }
 
-async fn close_socket(mut socket: axum::extract::ws::WebSocket, reason: &'static str) {
-    _ = tokio::time::timeout(Duration::from_secs(5), async move {
-        _ = socket
-            .send(axum::extract::ws::Message::Close(Some(
-                axum::extract::ws::CloseFrame {
-                    code: 1000,
-                    reason: reason.into(),
-                },
-            )))
-            .await;
-        _ = socket.close().await;
-    })
-    .await;
+#[cfg(test)]
+mod tests {
+    use super::check_upload;
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn only_the_author_can_upload_registered_images() {
+        let state = state();
+        add_user(&state, "author").await;
+        add_user(&state, "someone").await;
+        let session = log_in(&state, "author").await;
+
+        let (status, post_id) = request(
+            &state,
+            Method::POST,
+            "/api/post/create/start",
+            Some(&session),
+            Some(json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let post_id = String::from_utf8(post_id.to_vec()).unwrap();
+        let (status, _) = request(
+            &state,
+            Method::POST,
+            &format!("/api/post/create/image/{post_id}"),
+            Some(&session),
+            Some(json!({ "name": "cat.png" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = request(
+            &state,
+            Method::GET,
+            &format!("/api/post/create/image/{post_id}/cat.png"),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        assert_eq!(
+            check_upload(&state, "author", &post_id, "cat.png").await,
+            Ok(())
+        );
+        assert_eq!(
+            check_upload(&state, "someone", &post_id, "cat.png").await,
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            check_upload(&state, "author", &post_id, "dog.png").await,
+            Err(StatusCode::NOT_FOUND)
+        );
+
+        state.end_upload(&post_id, "cat.png", true).await;
+        assert_eq!(
+            check_upload(&state, "author", &post_id, "cat.png").await,
+            Err(StatusCode::CONFLICT)
+        );
+    }
 }
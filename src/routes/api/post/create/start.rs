@@ -1,4 +1,5 @@
-use crate::blog::{PostID, SessionID};
+use crate::blog::PostID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
 use crate::state::SharedState;
 use axum::extract::State;
 use axum::http::StatusCode;
@@ -8,32 +9,28 @@ use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub(super) struct PostOptions {
-    session: SessionID,
     #[serde(default)]
     reply_to: Option<PostID>,
+    #[serde(default, alias = "is_private")]
+    visibility: crate::blog::Visibility,
     #[serde(default)]
-    is_private: bool,
+    audience: Vec<crate::blog::CircleID>,
 }
 
 pub(super) async fn post(
     State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::CreatePost>,
     Json(request): Json<PostOptions>,
 ) -> Result<Response, StatusCode> {
-    let Some(session) = state.get_session(&request.session).await else {
-        return Err(StatusCode::UNAUTHORIZED);
-    };
-
-    let user = crate::routes::api::user::get(axum::extract::Path(session.for_username))
-        .await?
-        .0;
+    let user = crate::routes::api::user::read(&state, &username).await?;
     if !user.permissions.can_create_posts {
         return Err(StatusCode::FORBIDDEN);
     }
+    if !user.has_circles(&request.audience) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
     let new_post_id = crate::blog::get_random_hex_string::<{ crate::blog::POST_ID_BYTES }>();
-    let post_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("post")
-        .join(&new_post_id);
 
     let initial_post_jobs = get_initial_post_jobs(&request);
     let new_post_meta = crate::blog::Post {
@@ -44,51 +41,47 @@ pub(super) async fn post(
         replies: Vec::new(),
         quotes: Vec::new(),
         in_progress: true,
-        is_private: request.is_private, // TODO: add separate endpoint for setting `post.private`
+        visibility: request.visibility,
+        audience: request.audience,
+        edited_at: None,
     };
 
-    state.posts_in_progress.write().await.insert(
-        new_post_id.clone(),
-        crate::state::incomplete::IncompletePost {
-            meta: new_post_meta.clone(),
-            jobs_left: initial_post_jobs.iter().copied().collect(),
-            media: crate::state::incomplete::Media::default(),
-        },
-    );
-
-    tokio::spawn(async move { state.cleanup_stale_posts().await });
+    let new_post = crate::state::incomplete::IncompletePost {
+        meta: new_post_meta.clone(),
+        jobs_left: initial_post_jobs.iter().copied().collect(),
+        media: crate::state::incomplete::Media::default(),
+    };
 
-    match tokio::fs::create_dir(&post_path).await {
+    match state.store.put_post(&new_post_meta).await {
         Ok(()) => (),
         Err(err) => {
-            eprintln!("Could not create folder for post {new_post_id}: {err}");
+            eprintln!("Could not create meta for post {new_post_id}: {err}");
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
-
-    match tokio::fs::write(
-        post_path.join("meta.json"),
-        serde_json::to_vec(&new_post_meta).expect("value should serialize"),
-    )
-    .await
-    {
+    match state.store.put_incomplete(&new_post).await {
         Ok(()) => (),
         Err(err) => {
-            eprintln!("Could not create meta {post_path:?}: {err}");
+            eprintln!("Could not create draft state for post {new_post_id}: {err}");
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
 
+    state
+        .posts_in_progress
+        .write()
+        .await
+        .insert(new_post_id.clone(), new_post);
+
+    tokio::spawn(async move { state.cleanup_stale_posts().await });
+
     Ok((StatusCode::CREATED, new_post_id).into_response())
 }
 
 fn get_initial_post_jobs(request: &PostOptions) -> &'static [crate::job::PostJob] {
     if request.reply_to.is_some() {
-        &[
-            crate::job::PostJob::AddText,
-            crate::job::PostJob::ReplyParent,
-        ]
+        &[crate::job::add_text::JOB, crate::job::reply::JOB]
     } else {
-        &[crate::job::PostJob::AddText]
+        &[crate::job::add_text::JOB]
     }
 }
@@ -1,39 +1,25 @@
-use crate::blog::{PostID, SessionID};
+use crate::blog::PostID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
 use crate::state::SharedState;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
-use axum::Json;
-use serde::Deserialize;
-
-#[derive(Debug, Deserialize)]
-pub(super) struct DeleteOptions {
-    session: SessionID,
-}
 
 pub(super) async fn post(
     State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::DeletePost>,
     Path(post_id): Path<PostID>,
-    Json(request): Json<DeleteOptions>,
 ) -> StatusCode {
-    let Some(session) = state.get_session(&request.session).await else {
-        return StatusCode::UNAUTHORIZED;
-    };
-    let post = match super::meta::get(Path(post_id.clone())).await {
-        Ok(it) => it.0,
+    let post_lock = state.lock_post(&post_id).await;
+    let post = match super::meta::read(&state, &post_id).await {
+        Ok(it) => it,
         Err(err) => return err,
     };
 
-    if post.author_username != session.for_username {
+    if post.author_username != username {
         return StatusCode::FORBIDDEN;
     }
 
-    match tokio::fs::remove_dir_all(
-        std::path::Path::new(crate::blog::STORE_PATH)
-            .join("post")
-            .join(&post_id),
-    )
-    .await
-    {
+    match state.store.delete_post(&post_id).await {
         Ok(()) => (),
         Err(err) => {
             eprintln!("Error deleting files for post {post_id}: {err}");
@@ -41,20 +27,16 @@ pub(super) async fn post(
         }
     }
 
-    let mut user = match crate::routes::api::user::get(Path(session.for_username.clone())).await {
-        Ok(it) => it.0,
+    drop(post_lock);
+
+    let _user_lock = state.lock_user(&username).await;
+    let mut user = match crate::routes::api::user::read(&state, &username).await {
+        Ok(it) => it,
         Err(err) => return err,
     };
     user.posts.retain(|id| *id != post_id);
 
-    match tokio::fs::write(
-        std::path::Path::new(crate::blog::STORE_PATH)
-            .join("user")
-            .join(format!("{}.json", session.for_username)),
-        serde_json::to_vec(&user).expect("user should serialize"),
-    )
-    .await
-    {
+    match state.store.put_user(&user).await {
         Ok(()) => (),
         Err(err) => {
             eprintln!("Error updating user for deleted post {post_id}: {err}");
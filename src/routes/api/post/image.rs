@@ -1,12 +1,15 @@
 use crate::blog::PostID;
-use axum::extract::{Path, Query};
+use crate::routes::api::viewer::Viewer;
+use crate::state::SharedState;
+use crate::store::MediaSize;
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use serde::Deserialize;
 use tokio_util::io::ReaderStream;
 
 #[derive(Debug, Deserialize)]
-pub(super) struct ImageQueryOptions {
+pub(in crate::routes::api) struct ImageQueryOptions {
     #[serde(default)]
     pub raw: bool,
     #[serde(default)]
@@ -14,28 +17,19 @@ pub(super) struct ImageQueryOptions {
 }
 
 pub(super) async fn get(
+    State(state): SharedState,
+    viewer: Viewer,
     Path((post, image)): Path<(PostID, String)>,
     Query(options): Query<ImageQueryOptions>,
 ) -> Result<Response, StatusCode> {
-    let image_file_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("post")
-        .join(&post)
-        .join("image")
-        .join(if options.raw {
-            "raw"
-        } else if options.large {
-            "large"
-        } else {
-            "small"
-        })
-        .join(&image);
+    viewer.get_post(&state, post.clone()).await?;
+
+    let size = options.size();
 
-    let file = match tokio::fs::File::open(&image_file_path).await {
-        Ok(it) => it,
+    let file = match state.store.open_media(&post, size, &image).await {
+        Ok(Some(it)) => it,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
         Err(err) => {
-            if err.kind() == std::io::ErrorKind::NotFound {
-                return Err(StatusCode::NOT_FOUND);
-            }
             eprintln!("Error reading image {image} for post {post}: {err}");
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
@@ -43,9 +37,21 @@ pub(super) async fn get(
     let stream = ReaderStream::new(file);
     let stream = axum::body::Body::from_stream(stream);
 
-    if let Some(mime_guess) = new_mime_guess::from_path(&image_file_path).first() {
+    if let Some(mime_guess) = new_mime_guess::from_path(&image).first() {
         Ok(([("Content-Type", mime_guess.to_string())], stream).into_response())
     } else {
         Ok(stream.into_response())
     }
 }
+
+impl ImageQueryOptions {
+    pub fn size(&self) -> MediaSize {
+        if self.raw {
+            MediaSize::Raw
+        } else if self.large {
+            MediaSize::Large
+        } else {
+            MediaSize::Small
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use crate::blog::{Post, STORE_PATH};
+use crate::blog::Post;
+use crate::routes::api::viewer::Viewer;
 use crate::state::SharedState;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
@@ -7,125 +8,103 @@ use axum::Json;
 // TODO: pagination and/or different lengths
 pub(super) async fn get(
     State(state): SharedState,
+    viewer: Viewer,
     region: Option<Path<(usize, usize)>>,
 ) -> Result<Json<Vec<Post>>, StatusCode> {
     let Path((amount, after)) = region.unwrap_or(Path((10, 0)));
+    let amount = amount.min(crate::blog::MAX_LATEST_POSTS);
+    let wanted_amount = amount.saturating_add(after);
+
+    // private posts the viewer can't see are skipped, so more posts than asked
+    // for may need to be read
+    let mut read_amount = wanted_amount;
+    loop {
+        let latest_posts = get_cached_latest_posts(&state, read_amount).await?;
+        let is_everything = latest_posts.len() < read_amount;
+
+        let mut visible_posts = Vec::with_capacity(latest_posts.len());
+        for post in latest_posts {
+            if viewer.can_view(&state, &post).await? {
+                visible_posts.push(post);
+            }
+        }
 
-    let cache_len =
-        if let Some(lastest_posts_cache) = state.cache.read().await.latest_posts.as_ref() {
-            lastest_posts_cache.len()
-        } else {
-            0
-        };
-
-    if cache_len >= amount + after {
-        Ok(Json(
-            state
-                .cache
-                .read()
-                .await
-                .latest_posts
-                .as_ref()
-                .expect("cache_len should be greater than zero")
-                .iter()
-                .skip(after)
-                .take(amount)
-                .cloned()
-                .collect(),
-        ))
-    } else {
-        let latest_posts = get_latest_posts(amount + after).await?;
-        state.cache.write().await.latest_posts = Some(latest_posts.clone());
-
-        Ok(Json(
-            latest_posts.into_iter().skip(after).take(amount).collect(),
-        ))
+        if visible_posts.len() >= wanted_amount || is_everything {
+            return Ok(Json(
+                visible_posts.into_iter().skip(after).take(amount).collect(),
+            ));
+        }
+        read_amount = read_amount.saturating_mul(2);
     }
 }
 
-async fn get_latest_posts(amount: usize) -> Result<Vec<Post>, StatusCode> {
-    let mut latest_posts: Vec<Post> = Vec::with_capacity(amount);
-
-    'users: for user in get_all_users().await? {
-        // user posts are stored in chronological order
-        for post in user.posts.into_iter().rev() {
-            let post = super::meta::get(Path(post)).await?.0;
-            if post.in_progress || post.is_reply() {
-                continue;
-            }
-
-            if let Some(last_post) = latest_posts.last() {
-                if post.timestamp < last_post.timestamp {
-                    if latest_posts.len() < amount {
-                        latest_posts.push(post);
-                        continue;
-                    }
-                    continue 'users;
-                }
-            } else {
-                latest_posts.push(post);
-                continue;
-            }
-
-            for cursor in (0..latest_posts.len()).rev() {
-                if post.timestamp < latest_posts[cursor].timestamp {
-                    latest_posts.insert(cursor, post);
-                    break;
-                }
-                if cursor == 0 {
-                    latest_posts.insert(0, post);
-                    break; // redundant break but the compiler isnt smart enough to tell (yet!)
-                }
-            }
-
-            if latest_posts.len() > amount {
-                latest_posts.pop();
-            }
+async fn get_cached_latest_posts(
+    state: &std::sync::Arc<crate::state::State>,
+    amount: usize,
+) -> Result<Vec<Post>, StatusCode> {
+    if let Some(latest_posts_cache) = state.cache.read().await.latest_posts.as_ref() {
+        if latest_posts_cache.len() >= amount {
+            return Ok(latest_posts_cache.iter().take(amount).cloned().collect());
         }
     }
 
+    let latest_posts = get_latest_posts(state, amount).await?;
+    state.cache.write().await.latest_posts = Some(latest_posts.clone());
+
     Ok(latest_posts)
 }
 
-async fn get_all_users() -> Result<Vec<crate::blog::User>, StatusCode> {
-    let mut users_files =
-        match tokio::fs::read_dir(std::path::Path::new(STORE_PATH).join("user")).await {
-            Ok(it) => it,
-            Err(err) => {
-                eprintln!("Error reading users folder: {err}");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
-
-    let mut users = Vec::<crate::blog::User>::new();
-
-    while let Some(user) = users_files.next_entry().await.transpose() {
-        let user = match user {
-            Ok(it) => it,
-            Err(err) => {
-                eprintln!("Error reading file in users folder: {err}");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
-
-        let user_path = user.path();
-
-        let user = match tokio::fs::read(user.path()).await {
-            Ok(it) => it,
-            Err(err) => {
-                eprintln!("Error reading file {user_path:?} in users folder: {err}",);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
-
-        match serde_json::from_slice(&user) {
-            Ok(user) => users.push(user),
-            Err(err) => {
-                eprintln!("Error reading file {user_path:?} in users folder: {err}",);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
+async fn get_latest_posts(
+    state: &std::sync::Arc<crate::state::State>,
+    amount: usize,
+) -> Result<Vec<Post>, StatusCode> {
+    match state.store.latest_posts(amount).await {
+        Ok(it) => Ok(it),
+        Err(err) => {
+            eprintln!("Error reading latest posts: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
+}
 
-    Ok(users)
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, state};
+    use axum::http::{Method, StatusCode};
+
+    #[tokio::test]
+    async fn huge_amounts_are_capped() {
+        let state = state();
+        let mut author = add_user(&state, "author").await;
+        for index in 0..crate::blog::MAX_LATEST_POSTS + 5 {
+            let post = crate::store::tests::post(&format!("post-{index}"), "author");
+            state.store.put_post(&post).await.unwrap();
+            author.posts.push(post.id);
+        }
+        state.store.put_user(&author).await.unwrap();
+
+        let (status, latest) = request(
+            &state,
+            Method::GET,
+            &format!("/api/post/latest/{}/0", usize::MAX),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let latest: Vec<crate::blog::Post> = serde_json::from_slice(&latest).unwrap();
+        assert_eq!(latest.len(), crate::blog::MAX_LATEST_POSTS);
+
+        let (status, latest) = request(
+            &state,
+            Method::GET,
+            &format!("/api/post/latest/{}/{}", usize::MAX, usize::MAX),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(&latest[..], b"[]");
+    }
 }
@@ -1,37 +1,30 @@
-use crate::blog::{PostID, STORE_PATH};
-use axum::extract::Path;
+use crate::blog::PostID;
+use crate::routes::api::viewer::Viewer;
+use crate::state::SharedState;
+use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::Json;
 
-// TODO: return 404 when getting anything relating to private posts
 pub(super) async fn get(
+    State(state): SharedState,
+    viewer: Viewer,
     Path(post_id): Path<PostID>,
 ) -> Result<Json<crate::blog::Post>, StatusCode> {
-    let file = match tokio::fs::read(
-        std::path::Path::new(STORE_PATH)
-            .join("post")
-            .join(&post_id)
-            .join("meta.json"),
-    )
-    .await
-    {
-        Ok(it) => it,
-        Err(err) => {
-            if err.kind() == std::io::ErrorKind::NotFound {
-                return Err(StatusCode::NOT_FOUND);
-            }
-            eprintln!("Error reading post {post_id} meta: {err}");
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+    viewer.get_post(&state, post_id).await.map(Json)
+}
 
-    let post = match serde_json::from_slice(&file) {
-        Ok(it) => it,
+/// Reads a post regardless of who can see it. Anything returned to a client
+/// should go through [`Viewer::get_post`] instead.
+pub(in crate::routes::api) async fn read(
+    state: &crate::state::State,
+    post_id: &PostID,
+) -> Result<crate::blog::Post, StatusCode> {
+    match state.store.get_post(post_id).await {
+        Ok(Some(post)) => Ok(post),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
         Err(err) => {
-            eprintln!("Error deserializing post {post_id} meta: {err}");
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            eprintln!("Error reading post {post_id} meta: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
-    };
-
-    Ok(Json(post))
+    }
 }
@@ -6,8 +6,15 @@ mod delete;
 mod image;
 mod latest;
 mod meta;
+mod revisions;
+mod status;
 mod text;
 mod thread;
+mod visibility;
+
+pub(super) use image::ImageQueryOptions;
+pub(super) use meta::read as read_meta;
+pub(super) use text::render_markdown;
 
 pub fn route() -> NestedRouter {
     let image_compression_layer = tower_http::compression::CompressionLayer::new()
@@ -16,14 +23,19 @@ pub fn route() -> NestedRouter {
 
     axum::Router::new()
         .route("/:id/meta", get(meta::get))
-        .route("/:id/text", get(text::get))
-        .route("/:id/text/member", put(text::get_with_session))
+        .route("/:id/status", get(status::get))
+        .route("/:id/status/stream", get(status::stream))
+        .route("/:id/text", get(text::get).put(text::put))
+        .route("/:id/revisions", get(revisions::list))
+        .route("/:id/revisions/:number", get(revisions::get))
+        .route("/:id/revisions/:number/diff/:to", get(revisions::diff))
         .route("/latest/:amount/:after", get(latest::get))
         .route(
             "/:id/image/:img",
             get(image::get).layer(image_compression_layer),
         )
         .route("/:id/delete", post(delete::post))
+        .route("/:id/visibility", put(visibility::put))
         .route("/thread/:id", get(thread::get))
         .nest("/create", create::route())
 }
@@ -0,0 +1,130 @@
+use crate::blog::{Post, PostID, Revision};
+use crate::routes::api::viewer::Viewer;
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::Html;
+use axum::Json;
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+pub(super) struct RevisionInfo {
+    number: u32,
+    written_at: chrono::DateTime<chrono::Utc>,
+    /// whether this is the text the post currently shows
+    current: bool,
+}
+
+/// Every version of the post's text, oldest first. The last one is the
+/// current text.
+pub(super) async fn list(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path(post_id): Path<PostID>,
+) -> Result<Json<Vec<RevisionInfo>>, StatusCode> {
+    let meta = viewable_meta(&state, &viewer, post_id).await?;
+    let revisions = list_revisions(&state, &meta.id).await?;
+
+    let current_number = current_number(&revisions);
+    let mut infos = revisions
+        .into_iter()
+        .map(|revision| RevisionInfo {
+            number: revision.number,
+            written_at: revision.written_at,
+            current: false,
+        })
+        .collect::<Vec<_>>();
+    infos.push(RevisionInfo {
+        number: current_number,
+        written_at: meta.edited_at.unwrap_or(meta.timestamp),
+        current: true,
+    });
+
+    Ok(Json(infos))
+}
+
+pub(super) async fn get(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path((post_id, number)): Path<(PostID, u32)>,
+) -> Result<Html<Vec<u8>>, StatusCode> {
+    let meta = viewable_meta(&state, &viewer, post_id).await?;
+    let text = get_version(&state, &meta, number).await?;
+
+    Ok(Html(super::text::render(text, &meta.id).await?))
+}
+
+/// A unified diff of the Markdown between two versions
+pub(super) async fn diff(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path((post_id, from, to)): Path<(PostID, u32, u32)>,
+) -> Result<String, StatusCode> {
+    let meta = viewable_meta(&state, &viewer, post_id).await?;
+    let from_text = get_version(&state, &meta, from).await?;
+    let to_text = get_version(&state, &meta, to).await?;
+
+    Ok(similar::TextDiff::from_lines(&from_text, &to_text)
+        .unified_diff()
+        .header(&format!("revision {from}"), &format!("revision {to}"))
+        .to_string())
+}
+
+async fn viewable_meta(
+    state: &Arc<crate::state::State>,
+    viewer: &Viewer,
+    post_id: PostID,
+) -> Result<Post, StatusCode> {
+    let meta = viewer.get_post(state, post_id).await?;
+
+    if meta.in_progress {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(meta)
+}
+
+/// Reads a previous revision, or the current text if `number` is the newest
+async fn get_version(
+    state: &crate::state::State,
+    meta: &Post,
+    number: u32,
+) -> Result<String, StatusCode> {
+    match state.store.get_revision(&meta.id, number).await {
+        Ok(Some(revision)) => return Ok(revision.text),
+        Ok(None) => (),
+        Err(err) => {
+            eprintln!("Error reading revision {number} of post {}: {err}", meta.id);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    let revisions = list_revisions(state, &meta.id).await?;
+    if number != current_number(&revisions) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match state.store.get_text(&meta.id).await {
+        Ok(Some(text)) => Ok(text),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            eprintln!("Error reading post {} text: {err}", meta.id);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_revisions(
+    state: &crate::state::State,
+    post_id: &PostID,
+) -> Result<Vec<Revision>, StatusCode> {
+    state.store.list_revisions(post_id).await.map_err(|err| {
+        eprintln!("Error reading revisions for post {post_id}: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// The current text isn't stored as a revision, so it gets the next number
+pub(super) fn current_number(revisions: &[Revision]) -> u32 {
+    revisions.last().map_or(1, |revision| revision.number + 1)
+}
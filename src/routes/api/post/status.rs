@@ -0,0 +1,150 @@
+use crate::blog::PostID;
+use crate::job::PostJob;
+use crate::routes::api::viewer::Viewer;
+use crate::state::events::{PostEvent, PostEventKind};
+use crate::state::incomplete::IncompletePost;
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Json;
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+#[derive(Debug, Serialize)]
+pub(super) struct PostStatus {
+    in_progress: bool,
+    jobs_left: Vec<PostJob>,
+    images: Vec<ImageStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImageStatus {
+    name: String,
+    bytes_received: u64,
+    upload_finished: bool,
+}
+
+impl PostStatus {
+    fn from_incomplete(post: &IncompletePost) -> PostStatus {
+        PostStatus {
+            in_progress: true,
+            // keep a stable order instead of the set's
+            jobs_left: PostJob::all()
+                .filter(|job| post.jobs_left.contains(job))
+                .collect(),
+            images: post
+                .media
+                .images
+                .iter()
+                .map(|name| {
+                    let upload = post.media.uploads.get(name).cloned().unwrap_or_default();
+                    ImageStatus {
+                        name: name.clone(),
+                        bytes_received: upload.bytes_received,
+                        upload_finished: upload.finished,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+pub(super) async fn get(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path(post_id): Path<PostID>,
+) -> Result<Json<PostStatus>, StatusCode> {
+    viewer.get_post(&state, post_id.clone()).await?;
+    post_status(&state, &post_id).await.map(Json)
+}
+
+/// Sends the current status, then an event for each job that finishes until
+/// the post is published
+pub(super) async fn stream(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path(post_id): Path<PostID>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    viewer.get_post(&state, post_id.clone()).await?;
+
+    // subscribe first so nothing that happens while reading the status is missed
+    let events = state.subscribe_post_events();
+    let status = post_status(&state, &post_id).await?;
+
+    let updates = status.in_progress.then_some((state, post_id, events));
+    let initial = stream::once(async move { Event::default().event("status").json_data(status) });
+
+    Ok(
+        Sse::new(initial.chain(stream::unfold(updates, next_update)))
+            .keep_alive(KeepAlive::default()),
+    )
+}
+
+type Updates = (
+    Arc<crate::state::State>,
+    PostID,
+    broadcast::Receiver<PostEvent>,
+);
+
+async fn next_update(
+    updates: Option<Updates>,
+) -> Option<(Result<Event, axum::Error>, Option<Updates>)> {
+    let (state, post_id, mut events) = updates?;
+
+    let kind = loop {
+        match events.recv().await {
+            Ok(event) if event.post_id == post_id => break event.kind,
+            Ok(_) => continue,
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                // some events were missed, so send where things are now instead
+                let status = post_status(&state, &post_id).await.ok()?;
+                let done = !status.in_progress;
+                let event = Event::default().event("status").json_data(status);
+                return Some((event, (!done).then_some((state, post_id, events))));
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    };
+
+    let event = match kind {
+        PostEventKind::JobFinished(job) => Event::default().event("job").json_data(job),
+        PostEventKind::Published => Ok(Event::default().event("published").data(&post_id)),
+    };
+    let done = matches!(kind, PostEventKind::Published);
+    Some((event, (!done).then_some((state, post_id, events))))
+}
+
+async fn post_status(
+    state: &crate::state::State,
+    post_id: &PostID,
+) -> Result<PostStatus, StatusCode> {
+    // posts that are still waiting for their text are only in memory
+    if let Some(post) = state.posts_in_progress.read().await.get(post_id) {
+        return Ok(PostStatus::from_incomplete(post));
+    }
+
+    match state.store.get_incomplete(post_id).await {
+        Ok(Some(post)) => return Ok(PostStatus::from_incomplete(&post)),
+        Ok(None) => (),
+        Err(err) => {
+            eprintln!("Error reading draft state for post {post_id}: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    match state.store.get_post(post_id).await {
+        Ok(Some(post)) => Ok(PostStatus {
+            in_progress: post.in_progress,
+            jobs_left: Vec::new(),
+            images: Vec::new(),
+        }),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            eprintln!("Error reading post {post_id} meta: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::blog::{PostID, SessionID, STORE_PATH};
+use crate::blog::PostID;
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::routes::api::viewer::Viewer;
 use crate::state::SharedState;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
@@ -8,99 +10,159 @@ use comrak::nodes::NodeValue;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
-pub(super) struct TextOptions {
-    session: SessionID,
+pub(super) struct EditOptions {
+    text: String,
 }
 
-pub(super) async fn get(Path(post_id): Path<PostID>) -> Result<Html<Vec<u8>>, StatusCode> {
-    if let Some(html) = get_text(post_id, None).await? {
-        Ok(Html(html))
-    } else {
-        Err(StatusCode::FORBIDDEN)
-    }
-}
-
-pub(super) async fn get_with_session(
+pub(super) async fn get(
     State(state): SharedState,
+    viewer: Viewer,
     Path(post_id): Path<PostID>,
-    Json(request): Json<TextOptions>,
 ) -> Result<Html<Vec<u8>>, StatusCode> {
-    let Some(session) = state.get_session(&request.session).await else {
-        return Err(StatusCode::UNAUTHORIZED);
-    };
-
-    if let Some(html) = get_text(post_id, Some(&session.for_username)).await? {
-        Ok(Html(html))
-    } else {
-        Err(StatusCode::FORBIDDEN)
-    }
+    get_text(&state, &viewer, post_id).await.map(Html)
 }
 
 async fn get_text(
+    state: &std::sync::Arc<crate::state::State>,
+    viewer: &Viewer,
     post_id: PostID,
-    requesting_username: Option<&str>,
-) -> Result<Option<Vec<u8>>, StatusCode> {
-    let file = match tokio::fs::read_to_string(
-        std::path::Path::new(STORE_PATH)
-            .join("post")
-            .join(&post_id)
-            .join("text.md"),
-    )
-    .await
-    {
-        Ok(it) => it,
+) -> Result<Vec<u8>, StatusCode> {
+    viewer.get_post(state, post_id.clone()).await?;
+
+    let file = match state.store.get_text(&post_id).await {
+        Ok(Some(it)) => it,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
         Err(err) => {
-            if err.kind() == std::io::ErrorKind::NotFound {
-                return Err(StatusCode::NOT_FOUND);
-            }
             eprintln!("Error reading post {post_id} meta: {err}");
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
-    let post = post_id.clone();
-    let html = tokio::task::spawn_blocking(move || {
-        let arena = comrak::Arena::new();
-        let root = comrak::parse_document(&arena, &file, &comrak::Options::default());
+    render(file, &post_id).await
+}
 
-        process_nodes(root, &post);
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::CreatePost>,
+    Path(post_id): Path<PostID>,
+    Json(request): Json<EditOptions>,
+) -> StatusCode {
+    if request.text.trim().is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
 
-        let mut html = Vec::new();
-        comrak::format_html(root, &comrak::Options::default(), &mut html)?;
-        std::io::Result::Ok(html)
-    });
-    let html = match html.await.expect("task should not panic") {
+    let _post_lock = state.lock_post(&post_id).await;
+    let mut meta = match state.store.get_post(&post_id).await {
+        Ok(Some(it)) => it,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) => {
+            eprintln!("Error reading post {post_id} meta: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+
+    if meta.author_username != username {
+        return StatusCode::FORBIDDEN;
+    }
+    if meta.in_progress {
+        // drafts get their text from /create/finish
+        return StatusCode::CONFLICT;
+    }
+
+    let previous_text = match state.store.get_text(&post_id).await {
+        Ok(Some(it)) => it,
+        Ok(None) => return StatusCode::NOT_FOUND,
+        Err(err) => {
+            eprintln!("Error reading post {post_id} text: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+    if previous_text == request.text {
+        return StatusCode::OK;
+    }
+
+    let previous_revisions = match state.store.list_revisions(&post_id).await {
         Ok(it) => it,
         Err(err) => {
-            eprintln!("Couldn't post Markdown for post {post_id}: {err}");
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            eprintln!("Error reading revisions for post {post_id}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
         }
     };
+    let previous_revision = crate::blog::Revision {
+        number: super::revisions::current_number(&previous_revisions),
+        written_at: meta.edited_at.unwrap_or(meta.timestamp),
+        text: previous_text,
+    };
 
-    let meta = super::meta::get(Path(post_id)).await?.0;
+    // the old text is kept before it's overwritten, so a failure part way
+    // through at worst leaves a duplicate revision
+    match state.store.put_revision(&post_id, &previous_revision).await {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error writing revision for post {post_id}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    match state.store.put_text(&post_id, &request.text).await {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error writing text for post {post_id}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
 
-    if meta.is_private {
-        if let Some(username) = requesting_username {
-            let author_user = crate::routes::api::user::get(Path(meta.author_username))
-                .await?
-                .0;
-            if author_user.members.contains(username) || author_user.username == username {
-                return Ok(Some(html));
-            }
+    meta.edited_at = Some(chrono::Utc::now());
+    match state.store.put_post(&meta).await {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error writing post {post_id} meta: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
         }
-        Ok(None)
-    } else {
-        Ok(Some(html))
     }
+
+    // HACK: invalidates the whole cache when a change is made
+    state.cache.write().await.latest_posts = None;
+
+    StatusCode::OK
+}
+
+/// Turns a post's Markdown into HTML, pointing image links at the post's images
+pub(super) async fn render(text: String, post_id: &PostID) -> Result<Vec<u8>, StatusCode> {
+    render_markdown(text, Some(post_id.clone()))
+        .await
+        .map_err(|err| {
+            eprintln!("Couldn't post Markdown for post {post_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Turns Markdown into HTML. Without a post, `image:` links are left alone
+/// since there are no images for them to point to.
+pub(in crate::routes::api) async fn render_markdown(
+    text: String,
+    post_id: Option<PostID>,
+) -> std::io::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        let arena = comrak::Arena::new();
+        let root = comrak::parse_document(&arena, &text, &comrak::Options::default());
+
+        process_nodes(root, post_id.as_ref());
+
+        let mut html = Vec::new();
+        comrak::format_html(root, &comrak::Options::default(), &mut html)?;
+        std::io::Result::Ok(html)
+    })
+    .await
+    .expect("task should not panic")
 }
 
-fn process_nodes<'a>(node: &'a comrak::nodes::AstNode<'a>, post_id: &PostID) {
+fn process_nodes<'a>(node: &'a comrak::nodes::AstNode<'a>, post_id: Option<&PostID>) {
     process_node(node, post_id);
     for child in node.children() {
         process_nodes(child, post_id);
     }
 }
-fn process_node<'a>(node: &'a comrak::nodes::AstNode<'a>, post_id: &PostID) {
+fn process_node<'a>(node: &'a comrak::nodes::AstNode<'a>, post_id: Option<&PostID>) {
     match &mut node.data.borrow_mut().value {
         NodeValue::Image(link) | NodeValue::Link(link) => {
             process_link(link, post_id);
@@ -112,8 +174,8 @@ fn process_node<'a>(node: &'a comrak::nodes::AstNode<'a>, post_id: &PostID) {
     }
 }
 
-fn process_link(link: &mut comrak::nodes::NodeLink, post_id: &PostID) {
-    if let Some(post_image) = link.url.strip_prefix("image:") {
+fn process_link(link: &mut comrak::nodes::NodeLink, post_id: Option<&PostID>) {
+    if let (Some(post_id), Some(post_image)) = (post_id, link.url.strip_prefix("image:")) {
         link.url = format!("/api/post/{post_id}/image/{post_image}");
     }
     if let Some(username) = link.url.strip_prefix('@') {
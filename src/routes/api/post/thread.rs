@@ -1,27 +1,48 @@
 use crate::blog::{Post, PostID};
-use axum::extract::Path;
+use crate::routes::api::viewer::Viewer;
+use crate::state::SharedState;
+use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::Json;
 
 type LongestThreadFuture = dyn std::future::Future<Output = Result<Vec<Post>, StatusCode>> + Send;
 
-pub(super) async fn get(Path(post_id): Path<PostID>) -> Result<Json<Vec<Post>>, StatusCode> {
-    let thread = longest_thread(post_id).await?;
+pub(super) async fn get(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path(post_id): Path<PostID>,
+) -> Result<Json<Vec<Post>>, StatusCode> {
+    let thread = longest_thread(state, viewer, post_id).await?;
 
     // the previous implementation returned posts in reverse chonological order,
     // and its easier to write a clean frontend that way as well
     Ok(Json(thread.into_iter().rev().collect()))
 }
 
-async fn longest_thread(post_id: PostID) -> Result<Vec<Post>, StatusCode> {
+/// Replies the viewer can't see are left out, along with everything below them
+async fn longest_thread(
+    state: std::sync::Arc<crate::state::State>,
+    viewer: Viewer,
+    post_id: PostID,
+) -> Result<Vec<Post>, StatusCode> {
     fn longest_thread_inner(
+        state: std::sync::Arc<crate::state::State>,
+        viewer: Viewer,
         parent_post: Post,
     ) -> std::pin::Pin<std::boxed::Box<LongestThreadFuture>> {
         Box::pin(async move {
             let mut child_thread_set = tokio::task::JoinSet::new();
             for child_id in parent_post.replies.clone() {
-                let child_post = super::meta::get(Path(child_id)).await?.0;
-                child_thread_set.spawn(longest_thread_inner(child_post));
+                let child_post = match viewer.get_post(&state, child_id).await {
+                    Ok(it) => it,
+                    Err(StatusCode::NOT_FOUND) => continue,
+                    Err(err) => return Err(err),
+                };
+                child_thread_set.spawn(longest_thread_inner(
+                    state.clone(),
+                    viewer.clone(),
+                    child_post,
+                ));
             }
 
             let mut longest_child_thread: Option<Vec<Post>> = None;
@@ -53,8 +74,8 @@ async fn longest_thread(post_id: PostID) -> Result<Vec<Post>, StatusCode> {
         })
     }
 
-    let post = super::meta::get(Path(post_id)).await?.0;
-    let posts = longest_thread_inner(post).await?;
+    let post = viewer.get_post(&state, post_id).await?;
+    let posts = longest_thread_inner(state, viewer, post).await?;
 
     Ok(posts)
 }
@@ -0,0 +1,63 @@
+use crate::blog::{CircleID, PostID, Visibility};
+use crate::routes::api::authenticated::{scope, AuthenticatedUser};
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct VisibilityOptions {
+    #[serde(alias = "is_private")]
+    visibility: Visibility,
+    #[serde(default)]
+    audience: Vec<CircleID>,
+}
+
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser<scope::CreatePost>,
+    Path(post_id): Path<PostID>,
+    Json(request): Json<VisibilityOptions>,
+) -> StatusCode {
+    let _post_lock = state.lock_post(&post_id).await;
+    let mut post = match super::meta::read(&state, &post_id).await {
+        Ok(it) => it,
+        Err(err) => return err,
+    };
+
+    if post.author_username != username {
+        return StatusCode::FORBIDDEN;
+    }
+    if post.in_progress {
+        // the draft's copy of the meta would overwrite this when it's published
+        return StatusCode::CONFLICT;
+    }
+    if post.visibility == request.visibility && post.audience == request.audience {
+        return StatusCode::OK;
+    }
+
+    if !request.audience.is_empty() {
+        let author = match crate::routes::api::user::read(&state, &username).await {
+            Ok(user) => user,
+            Err(err) => return err,
+        };
+        if !author.has_circles(&request.audience) {
+            return StatusCode::BAD_REQUEST;
+        }
+    }
+
+    post.visibility = request.visibility;
+    post.audience = request.audience;
+    match state.store.put_post(&post).await {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error writing post {post_id} meta: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    state.cache.write().await.latest_posts = None; // HACK: invalidates the whole cache when a change is made
+
+    StatusCode::OK
+}
@@ -0,0 +1,59 @@
+use crate::auth::Login;
+use crate::routes::api::authenticated::session_response;
+use crate::state::session::Client;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct LoginCredentials {
+    pub username: String,
+    pub password: String,
+    /// from the user's authenticator app, or one of their recovery codes
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// Logs in. Users with an authenticator app also need a `code`; without one
+/// a right password gets a 401 with `WWW-Authenticate: TOTP`, so the client
+/// knows to ask for it.
+pub(super) async fn post(
+    State(state): SharedState,
+    client: Client,
+    Json(login_credentials): Json<LoginCredentials>,
+) -> Result<Response, Response> {
+    let auth = match crate::auth::Auth::validate(
+        &state.credentials,
+        &login_credentials.username,
+        login_credentials.password,
+        login_credentials.code,
+    )
+    .await
+    {
+        Ok(Login::Valid(it)) => it,
+        Ok(Login::Invalid) => return Err(StatusCode::UNAUTHORIZED.into_response()),
+        Ok(Login::CodeRequired) => {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                [(axum::http::header::WWW_AUTHENTICATE, "TOTP")],
+            )
+                .into_response())
+        }
+        Err(err) => {
+            eprintln!(
+                "Error validating credentials for user {:?}: {err}",
+                login_credentials.username
+            );
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+        }
+    };
+
+    Ok(session_response(
+        state
+            .create_session(login_credentials.username, client, auth)
+            .await,
+    ))
+}
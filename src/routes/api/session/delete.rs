@@ -0,0 +1,24 @@
+use crate::routes::api::authenticated::{clear_session_cookie, AuthenticatedUser};
+use crate::state::session::hash_session_id;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+/// Logs out of the session itself
+pub(super) async fn delete(
+    State(state): SharedState,
+    AuthenticatedUser {
+        username,
+        session_id,
+        ..
+    }: AuthenticatedUser,
+) -> Response {
+    match state.remove_session(&hash_session_id(&session_id)).await {
+        Ok(()) => (clear_session_cookie(), StatusCode::OK).into_response(),
+        Err(err) => {
+            eprintln!("Error deleting session for user {username}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
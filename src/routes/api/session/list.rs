@@ -0,0 +1,50 @@
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::session::{hash_session_id, SessionHash};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub(super) struct SessionInfo {
+    /// what to revoke it by
+    id: SessionHash,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_used_at: chrono::DateTime<chrono::Utc>,
+    expires_at: chrono::DateTime<chrono::Utc>,
+    user_agent: Option<String>,
+    ip: Option<std::net::IpAddr>,
+    /// whether it's the session making the request
+    current: bool,
+}
+
+/// Every session of the logged in user, oldest first
+pub(super) async fn get(
+    State(state): SharedState,
+    AuthenticatedUser {
+        username,
+        session_id,
+        ..
+    }: AuthenticatedUser,
+) -> Result<Json<Vec<SessionInfo>>, StatusCode> {
+    let current_hash = hash_session_id(&session_id);
+
+    let mut sessions = state
+        .sessions_of(&username)
+        .await
+        .into_iter()
+        .map(|(hash, session)| SessionInfo {
+            current: hash == current_hash,
+            id: hash,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at.unwrap_or(session.created_at),
+            expires_at: session.expires_at,
+            user_agent: session.client.user_agent,
+            ip: session.client.ip,
+        })
+        .collect::<Vec<_>>();
+    sessions.sort_by_key(|session| session.created_at);
+
+    Ok(Json(sessions))
+}
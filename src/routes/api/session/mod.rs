@@ -0,0 +1,42 @@
+use crate::state::session::Client;
+use crate::state::NestedRouter;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::routing::{get, post, put};
+
+mod create;
+mod delete;
+mod list;
+mod refresh;
+mod revoke;
+
+/// User agents longer than this are cut off before being stored
+const MAX_USER_AGENT_LEN: usize = 256;
+
+pub fn route() -> NestedRouter {
+    axum::Router::new()
+        .route("/", post(create::post).delete(delete::delete))
+        .route("/refresh", post(refresh::post))
+        .route("/list", get(list::get))
+        .route("/revoke", put(revoke::put))
+        .route("/revoke/others", put(revoke::put_others))
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Client {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Client {
+            user_agent: parts
+                .headers
+                .get(axum::http::header::USER_AGENT)
+                .and_then(|header| header.to_str().ok())
+                .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LEN).collect()),
+            ip: parts
+                .extensions
+                .get::<ConnectInfo<std::net::SocketAddr>>()
+                .map(|ConnectInfo(address)| address.ip()),
+        })
+    }
+}
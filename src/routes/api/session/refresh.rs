@@ -0,0 +1,27 @@
+use crate::blog::RefreshToken;
+use crate::routes::api::authenticated::session_response;
+use crate::state::session::Client;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct RefreshOptions {
+    refresh_token: RefreshToken,
+}
+
+/// Swaps a refresh token from logging in or an earlier refresh for a new
+/// session, without needing the password again
+pub(super) async fn post(
+    State(state): SharedState,
+    client: Client,
+    Json(request): Json<RefreshOptions>,
+) -> Result<Response, StatusCode> {
+    match state.refresh_session(&request.refresh_token, client).await {
+        Some(new_session) => Ok(session_response(new_session)),
+        None => Err(StatusCode::UNAUTHORIZED),
+    }
+}
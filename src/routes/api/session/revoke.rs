@@ -0,0 +1,60 @@
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::session::{hash_session_id, SessionHash};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct RevokeOptions {
+    /// from the session list
+    id: SessionHash,
+}
+
+/// Logs out one of the user's other sessions
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<RevokeOptions>,
+) -> StatusCode {
+    let is_own_session = state
+        .sessions_of(&username)
+        .await
+        .iter()
+        .any(|(hash, _)| *hash == request.id);
+    if !is_own_session {
+        return StatusCode::NOT_FOUND;
+    }
+
+    match state.remove_session(&request.id).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            eprintln!("Error revoking session for user {}: {err}", username);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Logs out every session of the user except the one making the request
+pub(super) async fn put_others(
+    State(state): SharedState,
+    AuthenticatedUser {
+        username,
+        session_id,
+        ..
+    }: AuthenticatedUser,
+) -> StatusCode {
+    let current_hash = hash_session_id(&session_id);
+
+    match state
+        .remove_sessions_of(&username, Some(&current_hash))
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            eprintln!("Error revoking sessions for user {}: {err}", username);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use crate::blog::{InviteID, SessionID};
+use crate::blog::InviteID;
+use crate::routes::api::authenticated::session_response;
+use crate::state::session::Client;
 use crate::state::SharedState;
 use axum::extract::State;
 use axum::http::StatusCode;
+use axum::response::Response;
 use axum::Json;
 use serde::Deserialize;
 
@@ -17,8 +20,9 @@ pub(super) struct SignupOptions {
 
 pub(super) async fn post(
     State(state): SharedState,
+    client: Client,
     Json(request): Json<SignupOptions>,
-) -> Result<SessionID, StatusCode> {
+) -> Result<Response, StatusCode> {
     if !request.is_valid() {
         return Err(StatusCode::BAD_REQUEST);
     }
@@ -27,7 +31,13 @@ pub(super) async fn post(
         return Err(StatusCode::NOT_FOUND);
     };
 
-    let auth = match crate::auth::Auth::write_entry(&request.username, request.password).await {
+    let auth = match crate::auth::Auth::write_entry(
+        &state.credentials,
+        &request.username,
+        request.password,
+    )
+    .await
+    {
         Ok(Some(auth)) => auth,
         Ok(None) => return Err(StatusCode::CONFLICT),
         Err(err) => {
@@ -45,26 +55,22 @@ pub(super) async fn post(
         posts: Vec::new(),
         permissions: invite.for_permissions,
         members: HashSet::new(),
+        circles: HashMap::new(),
+        member_requests: HashMap::new(),
+        profile: crate::blog::Profile::default(),
     };
 
-    match tokio::fs::write(
-        std::path::Path::new(crate::blog::STORE_PATH)
-            .join("user")
-            .join(format!("{}.json", request.username)),
-        serde_json::to_vec(&new_user).expect("user should serialize"),
-    )
-    .await
-    {
+    match state.store.put_user(&new_user).await {
         Ok(()) => (),
         Err(err) => {
-            eprintln!("Error writing new user {}.json: {err}", request.username);
+            eprintln!("Error writing new user {}: {err}", request.username);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     }
 
     state.remove_invite(&request.invite_id).await;
-    let auth_session_id = state.create_session(request.username, auth).await;
-    Ok(auth_session_id)
+    let new_session = state.create_session(request.username, client, auth).await;
+    Ok(session_response(new_session))
 }
 
 impl SignupOptions {
@@ -78,7 +84,33 @@ impl SignupOptions {
         if !username_pattern.is_match(&self.username) {
             return false;
         }
+        if super::user::RESERVED_USERNAMES.contains(&self.username.as_str()) {
+            return false;
+        }
 
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SignupOptions;
+
+    fn options(username: &str) -> SignupOptions {
+        SignupOptions {
+            invite_id: String::new(),
+            username: username.to_owned(),
+            name: String::new(),
+            password: String::new(),
+        }
+    }
+
+    #[test]
+    fn usernames_used_by_routes_are_rejected() {
+        assert!(options("someone").is_valid());
+        assert!(!options("some one").is_valid());
+        for username in super::super::user::RESERVED_USERNAMES {
+            assert!(!options(username).is_valid(), "{username}");
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::blog::ApiToken;
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::token::{Scope, TokenHash};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct TokenOptions {
+    name: String,
+    scopes: Vec<Scope>,
+    /// lasts until it's revoked if this isn't given
+    #[serde(default)]
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct NewToken {
+    /// goes wherever a session would. Only its hash is kept, so this is the
+    /// only time it can be shown.
+    token: ApiToken,
+    /// what to revoke it by
+    id: TokenHash,
+}
+
+/// Creates an API token. Only sessions from logging in can, so a token can't
+/// be used to give itself more scopes.
+pub(super) async fn post(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<TokenOptions>,
+) -> Result<Json<NewToken>, StatusCode> {
+    if !request.is_valid() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut scopes = request.scopes;
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    match state
+        .create_token(username.clone(), request.name, scopes, request.expires_at)
+        .await
+    {
+        Ok((token, id)) => Ok(Json(NewToken { token, id })),
+        Err(err) => {
+            eprintln!("Error creating token for user {}: {err}", username);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+impl TokenOptions {
+    fn is_valid(&self) -> bool {
+        !self.name.trim().is_empty()
+            && self.name.chars().count() <= MAX_NAME_LEN
+            && !self.scopes.is_empty()
+            && self
+                .expires_at
+                .is_none_or(|expires_at| chrono::Utc::now() < expires_at)
+    }
+}
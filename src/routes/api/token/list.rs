@@ -0,0 +1,41 @@
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::token::{Scope, TokenHash};
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub(super) struct TokenInfo {
+    /// what to revoke it by
+    id: TokenHash,
+    name: String,
+    scopes: Vec<Scope>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Every API token of the logged in user, oldest first
+pub(super) async fn get(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+) -> Result<Json<Vec<TokenInfo>>, StatusCode> {
+    let mut tokens = state
+        .tokens_of(&username)
+        .await
+        .into_iter()
+        .map(|(hash, token)| TokenInfo {
+            id: hash,
+            name: token.name,
+            scopes: token.scopes,
+            created_at: token.created_at,
+            last_used_at: token.last_used_at,
+            expires_at: token.expires_at,
+        })
+        .collect::<Vec<_>>();
+    tokens.sort_by_key(|token| token.created_at);
+
+    Ok(Json(tokens))
+}
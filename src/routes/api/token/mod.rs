@@ -0,0 +1,13 @@
+use crate::state::NestedRouter;
+use axum::routing::get;
+
+mod create;
+mod list;
+mod revoke;
+
+pub fn route() -> NestedRouter {
+    axum::Router::new().route(
+        "/",
+        get(list::get).post(create::post).delete(revoke::delete),
+    )
+}
@@ -0,0 +1,37 @@
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::token::TokenHash;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct RevokeOptions {
+    /// from the token list
+    id: TokenHash,
+}
+
+/// Stops one of the user's API tokens from working
+pub(super) async fn delete(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<RevokeOptions>,
+) -> StatusCode {
+    let is_own_token = state
+        .tokens_of(&username)
+        .await
+        .iter()
+        .any(|(hash, _)| *hash == request.id);
+    if !is_own_token {
+        return StatusCode::NOT_FOUND;
+    }
+
+    match state.remove_token(&request.id).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            eprintln!("Error revoking token for user {}: {err}", username);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use axum::extract::ws::{Message, WebSocket};
+use std::time::{Duration, Instant};
+
+const UPLOAD_SOCKET_TTL: Duration = Duration::from_secs(60);
+const UPLOAD_SOCKET_MESSAGE_TTL: Duration = Duration::from_secs(3);
+
+/// Where the chunks of an upload end up
+#[async_trait::async_trait]
+pub(super) trait ChunkWriter: Send {
+    async fn write_chunk(&mut self, data: &[u8]) -> std::io::Result<()>;
+}
+
+/// Writes binary messages to `writer` until the client closes the socket,
+/// returning whether it got that far. `description` is only for errors.
+pub(super) async fn receive_chunks(
+    mut socket: WebSocket,
+    description: &str,
+    writer: &mut impl ChunkWriter,
+) -> bool {
+    let mut total_recv_time = Duration::ZERO;
+
+    match socket.send(Message::Text("go ahead!".to_owned())).await {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error sending initial heartbeat: {err}");
+            return false;
+        }
+    }
+
+    loop {
+        let recv_start = Instant::now();
+        let message_or_timeout =
+            tokio::time::timeout(UPLOAD_SOCKET_MESSAGE_TTL, socket.recv()).await;
+        let Ok(message) = message_or_timeout else {
+            close_socket(socket, "message timeout").await;
+            return false;
+        };
+
+        total_recv_time += recv_start.elapsed();
+        if total_recv_time >= UPLOAD_SOCKET_TTL {
+            close_socket(socket, "transfer timeout").await;
+            return false;
+        }
+
+        let message = match message {
+            Some(Ok(it)) => it,
+            Some(Err(err)) => {
+                eprintln!("Error handling upload socket for {description}: {err}");
+                close_socket(socket, "connection error").await;
+                return false;
+            }
+            None => return false,
+        };
+
+        // HACK: the client will wait for the server to write the chunk it sent
+        // before sending a new one. This is slow, and should be replaced by a
+        // chunk indexing system in the future.
+        match message {
+            Message::Binary(data) => {
+                match writer.write_chunk(&data).await {
+                    Ok(()) => (),
+                    Err(err) => {
+                        eprintln!("Error writing to {description}: {err}");
+                        close_socket(socket, "server error").await;
+                        return false;
+                    }
+                }
+                match socket.send(Message::Text(String::new())).await {
+                    Ok(()) => (),
+                    Err(err) => {
+                        eprintln!("Error sending heartbeat: {err}");
+                        return false;
+                    }
+                }
+            }
+            Message::Close(_) => return true,
+            Message::Ping(_) | Message::Pong(_) | Message::Text(_) => (),
+        }
+    }
+}
+
+async fn close_socket(mut socket: WebSocket, reason: &'static str) {
+    _ = tokio::time::timeout(Duration::from_secs(5), async move {
+        _ = socket
+            .send(Message::Close(Some(axum::extract::ws::CloseFrame {
+                code: 1000,
+                reason: reason.into(),
+            })))
+            .await;
+        _ = socket.close().await;
+    })
+    .await;
+}
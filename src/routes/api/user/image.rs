@@ -0,0 +1,248 @@
+use crate::blog::{ProfileImage, UploadID};
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::routes::api::post::ImageQueryOptions;
+use crate::routes::api::upload::{receive_chunks, ChunkWriter};
+use crate::state::upload::ProfileUpload;
+use crate::state::SharedState;
+use crate::store::MediaSize;
+use axum::extract::ws::WebSocket;
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+
+pub(super) async fn get(
+    State(state): SharedState,
+    Path((username, image)): Path<(String, ProfileImage)>,
+    Query(options): Query<ImageQueryOptions>,
+) -> Result<Response, StatusCode> {
+    let user = super::read(&state, &username).await?;
+    let Some(image_name) = user.profile.image(image) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let data = match state
+        .store
+        .get_user_media(&username, options.size(), image_name)
+        .await
+    {
+        Ok(Some(it)) => it,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            eprintln!("Error reading {image_name} for user {username}: {err}");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    if let Some(mime_guess) = new_mime_guess::from_path(image_name).first() {
+        Ok(([("Content-Type", mime_guess.to_string())], data).into_response())
+    } else {
+        Ok(data.into_response())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ImageUploadOptions {
+    /// only used for its extension
+    name: String,
+}
+
+/// Starts replacing the image, returning the upload to connect a socket to
+pub(super) async fn post(
+    State(state): SharedState,
+    user: AuthenticatedUser,
+    Path((username, image)): Path<(String, ProfileImage)>,
+    Json(request): Json<ImageUploadOptions>,
+) -> Result<UploadID, StatusCode> {
+    if user.username != username {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let Some(extension) = std::path::Path::new(&request.name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .filter(|extension| image::ImageFormat::from_extension(extension).is_some())
+    else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+
+    // a new name every time, so the old image keeps being served until the
+    // new one is done. The file is only made by the first chunk, so uploads that
+    // are never connected to leave nothing behind.
+    let image_name = format!(
+        "{}-{}.{extension}",
+        image.name(),
+        crate::blog::get_random_hex_string::<8>()
+    );
+
+    Ok(state
+        .create_profile_upload(username, image, image_name)
+        .await)
+}
+
+pub(super) async fn ws(
+    State(state): SharedState,
+    Path((username, image, upload_id)): Path<(String, ProfileImage, UploadID)>,
+    socket: WebSocketUpgrade,
+) -> Response {
+    let Some(upload) = state.take_profile_upload(&upload_id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if upload.for_username != username || upload.image != image {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    socket.on_upgrade(|socket| handle_upload_socket(socket, state, upload))
+}
+
+struct ProfileImageChunks<'a> {
+    state: &'a crate::state::State,
+    upload: &'a ProfileUpload,
+}
+
+#[async_trait::async_trait]
+impl ChunkWriter for ProfileImageChunks<'_> {
+    async fn write_chunk(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.state
+            .store
+            .append_user_media(
+                &self.upload.for_username,
+                MediaSize::Raw,
+                &self.upload.name,
+                data,
+            )
+            .await
+    }
+}
+
+async fn handle_upload_socket(
+    socket: WebSocket,
+    state: std::sync::Arc<crate::state::State>,
+    upload: ProfileUpload,
+) {
+    let mut chunks = ProfileImageChunks {
+        state: &state,
+        upload: &upload,
+    };
+    let description = format!("{} for user {}", upload.name, upload.for_username);
+
+    if receive_chunks(socket, &description, &mut chunks).await
+        && finish_upload(&state, &upload).await
+    {
+        return;
+    }
+    delete_image(&state, &upload.for_username, &upload.name).await;
+}
+
+/// Makes the thumbnails and swaps the new image in for the old one
+async fn finish_upload(state: &crate::state::State, upload: &ProfileUpload) -> bool {
+    let username = &upload.for_username;
+
+    let raw = match state
+        .store
+        .get_user_media(username, MediaSize::Raw, &upload.name)
+        .await
+    {
+        Ok(Some(it)) => it,
+        Ok(None) => return false,
+        Err(err) => {
+            eprintln!("Error reading {} for user {username}: {err}", upload.name);
+            return false;
+        }
+    };
+
+    let thumbs = match crate::job::thumbnails::create_thumbs_of(&raw, &upload.name).await {
+        Ok(it) => it,
+        Err(err) => {
+            eprintln!(
+                "Error creating thumbnails of {} for user {username}: {err}",
+                upload.name
+            );
+            return false;
+        }
+    };
+    for (size, thumb) in thumbs {
+        match state
+            .store
+            .put_user_media(username, size, &upload.name, &thumb)
+            .await
+        {
+            Ok(()) => (),
+            Err(err) => {
+                eprintln!(
+                    "Error writing {} thumbnail of {} for user {username}: {err}",
+                    size.folder_name(),
+                    upload.name
+                );
+                return false;
+            }
+        }
+    }
+
+    let old_name = super::update(state, username, |user| {
+        Ok(user
+            .profile
+            .image_mut(upload.image)
+            .replace(upload.name.clone()))
+    })
+    .await;
+
+    match old_name {
+        Ok(Some(old_name)) => {
+            delete_image(state, username, &old_name).await;
+            true
+        }
+        Ok(None) => true,
+        Err(_) => false,
+    }
+}
+
+/// Removes every size of the image, some of which might not exist
+async fn delete_image(state: &crate::state::State, username: &str, image_name: &str) {
+    for size in enum_iterator::all::<MediaSize>() {
+        match state
+            .store
+            .delete_user_media(username, size, image_name)
+            .await
+        {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => {
+                eprintln!("Could not delete {image_name} for user {username}: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn unconnected_uploads_leave_no_files() {
+        let state = state();
+        add_user(&state, "someone").await;
+        let session = log_in(&state, "someone").await;
+
+        let (status, _) = request(
+            &state,
+            Method::POST,
+            "/api/user/someone/avatar",
+            Some(&session),
+            Some(json!({ "name": "avatar.png" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(state
+            .store
+            .list_user_media("someone")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
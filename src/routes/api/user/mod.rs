@@ -0,0 +1,64 @@
+use crate::blog::User;
+use crate::state::NestedRouter;
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
+
+mod image;
+mod password;
+mod password_reset;
+mod profile;
+mod totp;
+
+/// Taken by the routes below that aren't about a particular user, which would
+/// otherwise shadow those users' `/:id` routes
+pub(super) const RESERVED_USERNAMES: &[&str] = &["profile", "password", "totp"];
+
+pub fn route() -> NestedRouter {
+    axum::Router::new()
+        .route("/:id", get(profile::get))
+        .route("/:id/:image", get(image::get).post(image::post))
+        .route("/:id/:image/:upload_id", get(image::ws))
+        .route("/profile", put(profile::put))
+        .route("/password", put(password::put))
+        .route(
+            "/password/reset",
+            post(password_reset::post).put(password_reset::put),
+        )
+        .route(
+            "/totp",
+            post(totp::post).put(totp::put).delete(totp::delete),
+        )
+}
+
+/// Reads the whole user, which shouldn't be sent to anyone but that user
+pub(super) async fn read(state: &crate::state::State, username: &str) -> Result<User, StatusCode> {
+    match state.store.get_user(username).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            eprintln!("Error reading user {username}: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Applies `update` to the user and writes it back, holding the user's lock in
+/// between
+pub(super) async fn update<T>(
+    state: &crate::state::State,
+    username: &str,
+    update: impl FnOnce(&mut User) -> Result<T, StatusCode>,
+) -> Result<T, StatusCode> {
+    let _user_lock = state.lock_user(username).await;
+    let mut user = read(state, username).await?;
+
+    let result = update(&mut user)?;
+
+    match state.store.put_user(&user).await {
+        Ok(()) => Ok(result),
+        Err(err) => {
+            eprintln!("Error writing user {username}: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::session::hash_session_id;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct PasswordChangeOptions {
+    old_password: String,
+    new_password: String,
+}
+
+/// Changes the user's password and logs out their other sessions
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser {
+        username,
+        session_id,
+        ..
+    }: AuthenticatedUser,
+    Json(request): Json<PasswordChangeOptions>,
+) -> StatusCode {
+    if request.new_password.is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
+    match crate::auth::Auth::check_password(&state.credentials, &username, request.old_password)
+        .await
+    {
+        Ok(true) => (),
+        Ok(false) => return StatusCode::FORBIDDEN,
+        Err(err) => {
+            eprintln!("Error validating credentials for user {username:?}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    match crate::auth::Auth::set_password(&state.credentials, &username, request.new_password).await
+    {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error changing password for user {username}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    match state
+        .remove_sessions_of(&username, Some(&hash_session_id(&session_id)))
+        .await
+    {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            eprintln!("Error revoking sessions for user {username}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
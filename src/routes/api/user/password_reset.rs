@@ -0,0 +1,136 @@
+use crate::blog::PasswordResetID;
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub(super) struct PasswordResetOptions {
+    for_username: String,
+}
+
+/// Mints a single-use reset for someone else's account, to be passed on to
+/// them some other way
+pub(super) async fn post(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<PasswordResetOptions>,
+) -> Result<PasswordResetID, StatusCode> {
+    let user = super::read(&state, &username).await?;
+    if !user.permissions.can_reset_passwords {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    super::read(&state, &request.for_username).await?;
+
+    Ok(state.create_password_reset(request.for_username).await)
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct PasswordResetUseOptions {
+    reset_id: PasswordResetID,
+    new_password: String,
+}
+
+/// Sets a new password with a reset, logging out all of the user's sessions
+/// and revoking their API tokens
+pub(super) async fn put(
+    State(state): SharedState,
+    Json(request): Json<PasswordResetUseOptions>,
+) -> StatusCode {
+    if request.new_password.is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
+    // taken out before the password is set, so two requests can't both use it
+    let Some(password_reset) = state.remove_password_reset(&request.reset_id).await else {
+        return StatusCode::NOT_FOUND;
+    };
+    if !password_reset.is_valid() {
+        return StatusCode::NOT_FOUND;
+    }
+    let username = password_reset.for_username;
+
+    match crate::auth::Auth::set_password(&state.credentials, &username, request.new_password).await
+    {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error resetting password for user {username}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    match state.remove_sessions_of(&username, None).await {
+        Ok(()) => (),
+        Err(err) => {
+            eprintln!("Error revoking sessions for user {username}: {err}");
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    match state.remove_tokens_of(&username).await {
+        Ok(()) => StatusCode::OK,
+        Err(err) => {
+            eprintln!("Error revoking API tokens for user {username}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resets_work_once_and_log_out_everywhere() {
+        let state = state();
+        add_user(&state, "someone").await;
+        let now = chrono::Utc::now();
+        let credential = crate::auth::Credential {
+            hash: String::new(),
+            created_at: now,
+            updated_at: now,
+            failed_attempts: 0,
+            last_failed_at: None,
+            totp: None,
+        };
+        state
+            .credentials
+            .insert("someone", credential)
+            .await
+            .unwrap();
+        let session = log_in(&state, "someone").await;
+        state
+            .create_token("someone".to_owned(), "bot".to_owned(), Vec::new(), None)
+            .await
+            .unwrap();
+
+        let reset_id = state.create_password_reset("someone".to_owned()).await;
+        let body = json!({ "reset_id": reset_id, "new_password": "new password" });
+        let (first, second) = tokio::join!(
+            request(
+                &state,
+                Method::PUT,
+                "/api/user/password/reset",
+                None,
+                Some(body.clone())
+            ),
+            request(
+                &state,
+                Method::PUT,
+                "/api/user/password/reset",
+                None,
+                Some(body)
+            ),
+        );
+        let mut statuses = [first.0, second.0];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::NOT_FOUND]);
+
+        assert!(state.get_session(&session).await.is_none());
+        assert!(state.tokens_of("someone").await.is_empty());
+        assert!(state.store.list_tokens().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::blog::{PostID, ProfileLink};
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::routes::api::viewer::Viewer;
+use crate::state::SharedState;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+const MAX_LINKS: usize = 16;
+
+/// What anyone can see about a user. Members, circles, requests and
+/// permissions are only readable by the user themselves.
+#[derive(Debug, Serialize)]
+pub(super) struct PublicProfile {
+    username: String,
+    name: String,
+    /// the Markdown, for editing
+    bio: String,
+    bio_html: String,
+    avatar: Option<String>,
+    header: Option<String>,
+    links: Vec<ProfileLink>,
+    /// only the ones the viewer can see
+    posts: Vec<PostID>,
+    post_count: usize,
+    reply_count: usize,
+}
+
+pub(super) async fn get(
+    State(state): SharedState,
+    viewer: Viewer,
+    Path(username): Path<String>,
+) -> Result<Json<PublicProfile>, StatusCode> {
+    let user = super::read(&state, &username).await?;
+
+    let mut posts = Vec::new();
+    let mut post_count = 0;
+    let mut reply_count = 0;
+    for post_id in user.posts {
+        let post = match state.store.get_post(&post_id).await {
+            Ok(Some(post)) => post,
+            Ok(None) => continue,
+            Err(err) => {
+                eprintln!("Error reading post {post_id} meta: {err}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+        if !viewer.can_view(&state, &post).await? {
+            continue;
+        }
+
+        if post.is_reply() {
+            reply_count += 1;
+        } else {
+            post_count += 1;
+        }
+        posts.push(post_id);
+    }
+
+    let bio_html =
+        match crate::routes::api::post::render_markdown(user.profile.bio.clone(), None).await {
+            Ok(html) => String::from_utf8_lossy(&html).into_owned(),
+            Err(err) => {
+                eprintln!("Couldn't render bio for user {username}: {err}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        };
+
+    Ok(Json(PublicProfile {
+        avatar: user
+            .profile
+            .avatar
+            .map(|_| format!("/api/user/{username}/avatar")),
+        header: user
+            .profile
+            .header
+            .map(|_| format!("/api/user/{username}/header")),
+        username: user.username,
+        name: user.name,
+        bio: user.profile.bio,
+        bio_html,
+        links: user.profile.links,
+        posts,
+        post_count,
+        reply_count,
+    }))
+}
+
+/// Anything left out stays as it was
+#[derive(Debug, Deserialize)]
+pub(super) struct ProfileOptions {
+    name: Option<String>,
+    bio: Option<String>,
+    links: Option<Vec<ProfileLink>>,
+}
+
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<ProfileOptions>,
+) -> StatusCode {
+    if !request.is_valid() {
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let result = super::update(&state, &username, |user| {
+        if let Some(name) = request.name {
+            user.name = name;
+        }
+        if let Some(bio) = request.bio {
+            user.profile.bio = bio;
+        }
+        if let Some(links) = request.links {
+            user.profile.links = links;
+        }
+        Ok(())
+    })
+    .await;
+
+    match result {
+        Ok(()) => StatusCode::OK,
+        Err(err) => err,
+    }
+}
+
+impl ProfileOptions {
+    fn is_valid(&self) -> bool {
+        if self
+            .name
+            .as_ref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            return false;
+        }
+        let Some(links) = &self.links else {
+            return true;
+        };
+
+        // anything else, like `javascript:`, shouldn't end up in an href
+        links.len() <= MAX_LINKS
+            && links.iter().all(|link| {
+                !link.label.trim().is_empty()
+                    && (link.url.starts_with("https://") || link.url.starts_with("http://"))
+            })
+    }
+}
@@ -0,0 +1,115 @@
+use crate::auth::Credential;
+use crate::routes::api::authenticated::AuthenticatedUser;
+use crate::state::SharedState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub(super) struct TotpCodeOptions {
+    code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct TotpEnrollment {
+    /// base32, for typing into the app by hand
+    secret: String,
+    /// `otpauth://`, for showing as a QR code
+    uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct RecoveryCodes {
+    /// each works once instead of a code from the app. They aren't stored, so
+    /// this is the only time they can be shown.
+    recovery_codes: Vec<String>,
+}
+
+/// Starts enrolling an authenticator app. Codes aren't asked for when logging
+/// in until one has been confirmed with [`put`].
+pub(super) async fn post(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+) -> Result<Json<TotpEnrollment>, StatusCode> {
+    match state.credentials.begin_totp(&username).await {
+        Ok(Some(totp)) => Ok(Json(TotpEnrollment {
+            uri: totp.uri(&username),
+            secret: totp.secret,
+        })),
+        Ok(None) => Err(StatusCode::CONFLICT),
+        Err(err) => {
+            eprintln!("Error enrolling authenticator app for user {username}: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Confirms the app shows the right code, which turns it on
+pub(super) async fn put(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<TotpCodeOptions>,
+) -> Result<Json<RecoveryCodes>, StatusCode> {
+    let credential = read_credential(&state, &username).await?;
+    match credential.totp {
+        Some(totp) if !totp.is_enabled() => (),
+        Some(_) => return Err(StatusCode::CONFLICT),
+        None => return Err(StatusCode::NOT_FOUND),
+    }
+
+    match state
+        .credentials
+        .confirm_totp(&username, &request.code, chrono::Utc::now())
+        .await
+    {
+        Ok(Some(recovery_codes)) => Ok(Json(RecoveryCodes { recovery_codes })),
+        Ok(None) => Err(StatusCode::FORBIDDEN),
+        Err(err) => {
+            eprintln!("Error confirming authenticator app for user {username}: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Turns the app off, given a code from it or a recovery code
+pub(super) async fn delete(
+    State(state): SharedState,
+    AuthenticatedUser { username, .. }: AuthenticatedUser,
+    Json(request): Json<TotpCodeOptions>,
+) -> StatusCode {
+    let credential = match read_credential(&state, &username).await {
+        Ok(it) => it,
+        Err(err) => return err,
+    };
+    if !credential.totp.is_some_and(|totp| totp.is_enabled()) {
+        return StatusCode::NOT_FOUND;
+    }
+
+    match state
+        .credentials
+        .disable_totp(&username, &request.code, chrono::Utc::now())
+        .await
+    {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::FORBIDDEN,
+        Err(err) => {
+            eprintln!("Error disabling authenticator app for user {username}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn read_credential(
+    state: &crate::state::State,
+    username: &str,
+) -> Result<Credential, StatusCode> {
+    match state.credentials.get(username).await {
+        Ok(Some(credential)) => Ok(credential),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            eprintln!("Error reading credential for user {username}: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
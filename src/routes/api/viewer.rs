@@ -0,0 +1,222 @@
+use crate::blog::{Post, PostID, Visibility};
+use crate::state::token::Scope;
+use crate::state::State;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+use std::sync::Arc;
+
+/// Whoever is making the request, from an `Authorization: Bearer <session>`
+/// header or the session cookie. A missing or expired session means the request is treated as
+/// logged out rather than rejected, as does an API token without
+/// [`Scope::ReadPrivate`].
+#[derive(Debug, Clone, Default)]
+pub(super) struct Viewer {
+    pub username: Option<String>,
+}
+
+#[axum::async_trait]
+impl FromRequestParts<Arc<State>> for Viewer {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<State>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(session_id) = super::authenticated::request_session(&parts.headers) else {
+            return Ok(Viewer::default());
+        };
+
+        Ok(Viewer::from_username(
+            state.authorize(&session_id, Scope::ReadPrivate).await,
+        ))
+    }
+}
+
+impl Viewer {
+    pub fn from_username(username: Option<String>) -> Viewer {
+        Viewer { username }
+    }
+
+    pub async fn can_view(&self, state: &Arc<State>, post: &Post) -> Result<bool, StatusCode> {
+        if let Visibility::Public | Visibility::Unlisted = post.visibility {
+            return Ok(true);
+        }
+        let Some(username) = self.username.as_deref() else {
+            return Ok(false);
+        };
+        if post.author_username == username {
+            return Ok(true);
+        }
+        if post.visibility == Visibility::Author {
+            return Ok(false);
+        }
+
+        let author = super::user::read(state, &post.author_username).await?;
+        Ok(author.is_in_audience(post, username))
+    }
+
+    /// Reads a post's meta, as if it didn't exist when the viewer can't see it
+    pub async fn get_post(&self, state: &Arc<State>, post_id: PostID) -> Result<Post, StatusCode> {
+        let post = super::post::read_meta(state, &post_id).await?;
+
+        if self.can_view(state, &post).await? {
+            Ok(post)
+        } else {
+            Err(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blog::{Revision, Visibility};
+    use crate::routes::api::tests::request;
+    use crate::state::tests::{add_user, log_in, state};
+    use crate::store::MediaSize;
+    use axum::http::{Method, StatusCode};
+
+    /// Every route that reads a single post, for the post `hidden`
+    const POST_ROUTES: &[&str] = &[
+        "/api/post/hidden/meta",
+        "/api/post/hidden/text",
+        "/api/post/hidden/image/picture.png",
+        "/api/post/thread/hidden",
+        "/api/post/hidden/revisions",
+        "/api/post/hidden/revisions/1",
+        "/api/post/hidden/revisions/1/diff/2",
+        "/api/post/hidden/status",
+        "/api/post/hidden/status/stream",
+    ];
+
+    /// Sets up a published `hidden` post by `author` replying to a public
+    /// `parent`, with `member` as one of the author's members, then checks
+    /// which of `anonymous`, `stranger`, `member` and `author` can read it
+    async fn check_visibility(visibility: Visibility, can_view: [bool; 4]) {
+        let state = state();
+        let mut author = add_user(&state, "author").await;
+        author.members.insert("member".to_owned());
+        author.posts = vec!["parent".to_owned(), "hidden".to_owned()];
+        state.store.put_user(&author).await.unwrap();
+        add_user(&state, "stranger").await;
+        add_user(&state, "member").await;
+
+        let mut parent = crate::store::tests::post("parent", "author");
+        parent.replies.push("hidden".to_owned());
+        let mut hidden = crate::store::tests::post("hidden", "author");
+        hidden.reply_to = Some("parent".to_owned());
+        hidden.visibility = visibility;
+        hidden.timestamp = parent.timestamp + chrono::Duration::seconds(1);
+        state.store.put_post(&parent).await.unwrap();
+        state.store.put_post(&hidden).await.unwrap();
+        state.store.put_text(&parent.id, "parent").await.unwrap();
+        state.store.put_text(&hidden.id, "edited").await.unwrap();
+        let revision = Revision {
+            number: 1,
+            written_at: hidden.timestamp,
+            text: "original".to_owned(),
+        };
+        state
+            .store
+            .put_revision(&hidden.id, &revision)
+            .await
+            .unwrap();
+        state
+            .store
+            .put_media(&hidden.id, MediaSize::Small, "picture.png", b"png")
+            .await
+            .unwrap();
+
+        let viewers = [
+            None,
+            Some(log_in(&state, "stranger").await),
+            Some(log_in(&state, "member").await),
+            Some(log_in(&state, "author").await),
+        ];
+
+        for (viewer, can_view) in viewers.iter().zip(can_view) {
+            let expected = if can_view {
+                StatusCode::OK
+            } else {
+                StatusCode::NOT_FOUND
+            };
+            for route in POST_ROUTES {
+                let (status, _) = request(&state, Method::GET, route, viewer.as_ref(), None).await;
+                assert_eq!(status, expected, "{route} for {viewer:?}");
+            }
+
+            // lists leave the post out instead of failing
+            let (status, thread) = request(
+                &state,
+                Method::GET,
+                "/api/post/thread/parent",
+                viewer.as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let thread: Vec<crate::blog::Post> = serde_json::from_slice(&thread).unwrap();
+            assert_eq!(
+                thread.iter().any(|post| post.id == "hidden"),
+                can_view,
+                "thread for {viewer:?}"
+            );
+        }
+    }
+
+    /// Top-level posts, since replies aren't in `latest`
+    async fn check_latest_visibility(visibility: Visibility, can_view: [bool; 4]) {
+        let state = state();
+        let mut author = add_user(&state, "author").await;
+        author.members.insert("member".to_owned());
+        author.posts = vec!["hidden".to_owned()];
+        state.store.put_user(&author).await.unwrap();
+
+        let mut hidden = crate::store::tests::post("hidden", "author");
+        hidden.visibility = visibility;
+        state.store.put_post(&hidden).await.unwrap();
+
+        let viewers = [
+            None,
+            Some(log_in(&state, "stranger").await),
+            Some(log_in(&state, "member").await),
+            Some(log_in(&state, "author").await),
+        ];
+
+        for (viewer, can_view) in viewers.iter().zip(can_view) {
+            let (status, latest) = request(
+                &state,
+                Method::GET,
+                "/api/post/latest/10/0",
+                viewer.as_ref(),
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            let latest: Vec<crate::blog::Post> = serde_json::from_slice(&latest).unwrap();
+            assert_eq!(
+                latest.iter().any(|post| post.id == "hidden"),
+                can_view,
+                "latest for {viewer:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn public_posts_are_readable_by_everyone() {
+        check_visibility(Visibility::Public, [true; 4]).await;
+        check_latest_visibility(Visibility::Public, [true; 4]).await;
+    }
+
+    #[tokio::test]
+    async fn members_posts_are_only_readable_by_members() {
+        check_visibility(Visibility::Members, [false, false, true, true]).await;
+        check_latest_visibility(Visibility::Members, [false, false, true, true]).await;
+    }
+
+    #[tokio::test]
+    async fn author_posts_are_only_readable_by_the_author() {
+        check_visibility(Visibility::Author, [false, false, false, true]).await;
+        check_latest_visibility(Visibility::Author, [false, false, false, true]).await;
+    }
+}
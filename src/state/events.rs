@@ -0,0 +1,40 @@
+use crate::blog::PostID;
+use crate::job::PostJob;
+use serde::Serialize;
+
+/// How many events a slow subscriber can fall behind before it starts missing
+/// them
+const POST_EVENT_BUFFER: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct PostEvent {
+    pub post_id: PostID,
+    pub kind: PostEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostEventKind {
+    /// The job has run, or given up after too many attempts
+    JobFinished(PostJob),
+    /// The post is no longer in progress
+    Published,
+}
+
+pub fn channel() -> tokio::sync::broadcast::Sender<PostEvent> {
+    tokio::sync::broadcast::channel(POST_EVENT_BUFFER).0
+}
+
+impl super::State {
+    pub fn subscribe_post_events(&self) -> tokio::sync::broadcast::Receiver<PostEvent> {
+        self.post_events.subscribe()
+    }
+
+    pub fn send_post_event(&self, post_id: &PostID, kind: PostEventKind) {
+        // an error just means nobody is listening
+        _ = self.post_events.send(PostEvent {
+            post_id: post_id.clone(),
+            kind,
+        });
+    }
+}
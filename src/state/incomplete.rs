@@ -1,47 +1,63 @@
-use tokio::sync::RwLock;
-
-use crate::blog::Post;
+use crate::blog::{Post, PostID};
+use crate::job::queue::QueuedJob;
 use crate::job::PostJob;
-use std::collections::HashSet;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Clone)]
+/// Persisted next to the post's meta so drafts survive restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IncompletePost {
     pub meta: Post,
     pub jobs_left: HashSet<PostJob>,
     pub media: Media,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Media {
     pub images: Vec<String>,
     pub videos: Vec<String>,
+    /// keyed by image name
+    #[serde(default)]
+    pub uploads: HashMap<String, Upload>,
 }
 
-async fn write_post(post: &Post) {
-    let post_folder_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("post")
-        .join(&post.id);
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Upload {
+    pub bytes_received: u64,
+    /// whether the client closed the upload socket cleanly
+    pub finished: bool,
+}
 
-    match tokio::fs::write(
-        post_folder_path.join("meta.json"),
-        serde_json::to_vec(post).expect("post meta should serialize"),
-    )
-    .await
+async fn write_post(state: &super::State, mut post: Post) {
     {
-        Ok(()) => (),
-        Err(err) => {
-            eprintln!("Error writing meta for post {}: {err}", post.id);
+        let _post_lock = state.lock_post(&post.id).await;
+
+        // replies may have been linked to this post while it was in progress
+        match state.store.get_post(&post.id).await {
+            Ok(Some(stored_post)) => post.replies = stored_post.replies,
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("Error reading stored meta for post {}: {err}", post.id);
+            }
         }
-    }
 
-    let user_path = std::path::Path::new(crate::blog::STORE_PATH)
-        .join("user")
-        .join(format!("{}.json", post.author_username));
+        match state.store.put_post(&post).await {
+            Ok(()) => (),
+            Err(err) => {
+                eprintln!("Error writing meta for post {}: {err}", post.id);
+            }
+        }
+    }
 
-    let mut user = match tokio::fs::read(&user_path).await {
-        Ok(it) => {
-            serde_json::from_slice::<crate::blog::User>(&it).expect("user should deserialize")
+    let _user_lock = state.lock_user(&post.author_username).await;
+    let mut user = match state.store.get_user(&post.author_username).await {
+        Ok(Some(it)) => it,
+        Ok(None) => {
+            eprintln!(
+                "Author {} of post {} does not exist",
+                post.author_username, post.id
+            );
+            return;
         }
         Err(err) => {
             eprintln!(
@@ -53,12 +69,7 @@ async fn write_post(post: &Post) {
     };
     user.posts.push(post.id.clone());
 
-    match tokio::fs::write(
-        &user_path,
-        serde_json::to_vec(&user).expect("user should serialize"),
-    )
-    .await
-    {
+    match state.store.put_user(&user).await {
         Ok(()) => (),
         Err(err) => {
             eprintln!(
@@ -70,41 +81,123 @@ async fn write_post(post: &Post) {
 }
 
 impl super::State {
+    pub async fn record_upload_progress(&self, post_id: &PostID, image_name: &str, bytes: usize) {
+        let mut posts = self.posts_in_progress.write().await;
+        let Some(post) = posts.get_mut(post_id) else {
+            return;
+        };
+
+        post.media
+            .uploads
+            .entry(image_name.to_owned())
+            .or_default()
+            .bytes_received += bytes as u64;
+    }
+
+    /// Marks an upload as finished, or resets it if it failed, and persists the
+    /// draft. Progress in between isn't persisted, since that would mean a write
+    /// per chunk.
+    pub async fn end_upload(&self, post_id: &PostID, image_name: &str, finished: bool) {
+        let mut posts = self.posts_in_progress.write().await;
+        let Some(post) = posts.get_mut(post_id) else {
+            return;
+        };
+
+        let upload = post.media.uploads.entry(image_name.to_owned()).or_default();
+        if finished {
+            upload.finished = true;
+        } else {
+            *upload = Upload::default();
+        }
+
+        match self.store.put_incomplete(post).await {
+            Ok(()) => (),
+            Err(err) => eprintln!("Error writing draft state for post {post_id}: {err}"),
+        }
+    }
+
+    /// Queues the post's remaining jobs that aren't waiting on others. The post
+    /// is published once the last one has run.
     pub async fn complete_post(&self, post: IncompletePost) {
-        let jobs_left = post.jobs_left.clone();
-        let post = Arc::new(RwLock::new(post));
-
-        let mut set = tokio::task::JoinSet::new();
-        for job in jobs_left {
-            let spawn_post = post.clone();
-            match job {
-                PostJob::Thumbnails => set.spawn_blocking(move || {
-                    crate::job::thumbnails::run(&spawn_post.blocking_read());
-                }),
-                PostJob::ReplyParent => set.spawn(async move {
-                    crate::job::reply::run(&spawn_post.read().await.meta).await;
-                }),
-                PostJob::AddText => unreachable!(),
-            };
+        if post.jobs_left.is_empty() {
+            self.publish_post(post.meta).await;
+            return;
         }
 
-        while let Some(task_result) = set.join_next().await {
-            task_result.expect("task should not panic");
+        let ready_jobs = post
+            .jobs_left
+            .iter()
+            .filter(|job| job.is_ready(&post.jobs_left));
+        self.enqueue_post_jobs(&post.meta.id, ready_jobs).await;
+    }
+
+    async fn enqueue_post_jobs(&self, post_id: &PostID, jobs: impl Iterator<Item = &PostJob>) {
+        for job in jobs {
+            match self
+                .enqueue_job(QueuedJob::new(post_id.clone(), *job))
+                .await
+            {
+                Ok(()) => (),
+                Err(err) => eprintln!("Error queueing job {job} for post {post_id}: {err}"),
+            }
         }
+    }
 
-        let post = Arc::into_inner(post)
-            .expect("arc should have no refs")
-            .into_inner();
+    /// Called when a job has run (or given up), publishing the post if it was
+    /// the last one
+    pub async fn finish_post_job(&self, post_id: &PostID, job: PostJob) {
+        let post = {
+            let _post_lock = self.lock_post(post_id).await;
+            let mut post = match self.store.get_incomplete(post_id).await {
+                Ok(Some(it)) => it,
+                Ok(None) => return,
+                Err(err) => {
+                    eprintln!("Error reading draft state for post {post_id}: {err}");
+                    return;
+                }
+            };
 
+            post.jobs_left.remove(&job);
+            match self.store.put_incomplete(&post).await {
+                Ok(()) => (),
+                Err(err) => eprintln!("Error writing draft state for post {post_id}: {err}"),
+            }
+            post
+        };
+
+        self.send_post_event(post_id, super::events::PostEventKind::JobFinished(job));
+        if post.jobs_left.is_empty() {
+            self.publish_post(post.meta).await;
+            return;
+        }
+
+        // only the jobs that were waiting on this one, since the others are
+        // already queued or running
+        let unblocked_jobs = post
+            .jobs_left
+            .iter()
+            .filter(|left| left.dependencies().contains(&job) && left.is_ready(&post.jobs_left));
+        self.enqueue_post_jobs(post_id, unblocked_jobs).await;
+    }
+
+    async fn publish_post(&self, meta: Post) {
         let new_post = Post {
             in_progress: false,
             timestamp: chrono::Utc::now(),
-            ..post.meta
+            ..meta
         };
 
-        write_post(&new_post).await;
+        let post_id = new_post.id.clone();
+        write_post(self, new_post).await;
+
+        match self.store.delete_incomplete(&post_id).await {
+            Ok(()) => (),
+            Err(err) => eprintln!("Error deleting draft state for post {post_id}: {err}"),
+        }
 
         // HACK: invalidates the whole cache when a change is made
         self.cache.write().await.latest_posts = None;
+
+        self.send_post_event(&post_id, super::events::PostEventKind::Published);
     }
 }
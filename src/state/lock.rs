@@ -0,0 +1,48 @@
+use crate::blog::PostID;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// What a read-modify-write of stored data needs exclusive access to
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub enum LockKey {
+    User(String),
+    Post(PostID),
+}
+
+/// Hands out one async mutex per key. Mutexes are only kept alive by their
+/// guards, so keys nobody is holding don't pile up.
+#[derive(Debug, Default)]
+pub struct Locks {
+    locks: std::sync::Mutex<HashMap<LockKey, Weak<Mutex<()>>>>,
+}
+
+impl Locks {
+    pub async fn lock(&self, key: LockKey) -> OwnedMutexGuard<()> {
+        let mutex = {
+            let mut locks = self.locks.lock().expect("locks should not be poisoned");
+            locks.retain(|_, lock| lock.strong_count() > 0);
+
+            if let Some(mutex) = locks.get(&key).and_then(Weak::upgrade) {
+                mutex
+            } else {
+                let mutex = Arc::new(Mutex::new(()));
+                locks.insert(key, Arc::downgrade(&mutex));
+                mutex
+            }
+        };
+
+        mutex.lock_owned().await
+    }
+}
+
+impl super::State {
+    /// Must be held while reading, changing and writing back a user
+    pub async fn lock_user(&self, username: &str) -> OwnedMutexGuard<()> {
+        self.locks.lock(LockKey::User(username.to_owned())).await
+    }
+    /// Must be held while reading, changing and writing back a post's meta
+    pub async fn lock_post(&self, post_id: &PostID) -> OwnedMutexGuard<()> {
+        self.locks.lock(LockKey::Post(post_id.clone())).await
+    }
+}
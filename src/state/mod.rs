@@ -1,31 +1,53 @@
-use crate::blog::{InviteID, PostID, SessionID};
+use crate::blog::{InviteID, PasswordResetID, PostID, UploadID};
+use crate::store::Store;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
 pub mod cache;
+pub mod events;
 pub mod incomplete;
 pub mod invite;
+pub mod lock;
+pub mod password_reset;
 pub mod session;
+pub mod token;
+pub mod upload;
 
 pub type SharedState = axum::extract::State<Arc<State>>;
 pub type NestedRouter = axum::Router<Arc<State>>;
 
 #[derive(Debug)]
 pub struct State {
-    pub sessions: RwLock<HashMap<SessionID, session::Session>>,
+    pub store: Arc<dyn Store>,
+    pub credentials: crate::auth::Credentials,
+    pub sessions: RwLock<HashMap<session::SessionHash, session::Session>>,
+    pub tokens: RwLock<HashMap<token::TokenHash, token::Token>>,
     pub posts_in_progress: RwLock<HashMap<PostID, incomplete::IncompletePost>>,
     pub invites: RwLock<HashMap<InviteID, invite::Invite>>,
+    pub password_resets: RwLock<HashMap<PasswordResetID, password_reset::PasswordReset>>,
+    pub profile_uploads: RwLock<HashMap<UploadID, upload::ProfileUpload>>,
     pub cache: RwLock<cache::Cache>,
+    pub locks: lock::Locks,
+    pub jobs: crate::job::queue::JobQueue,
+    pub post_events: tokio::sync::broadcast::Sender<events::PostEvent>,
 }
 
 impl State {
-    pub fn new() -> State {
+    pub fn new(store: Arc<dyn Store>) -> State {
         State {
+            credentials: crate::auth::Credentials::new(store.clone()),
+            store,
             sessions: RwLock::new(HashMap::new()),
+            tokens: RwLock::new(HashMap::new()),
             posts_in_progress: RwLock::new(HashMap::new()),
             invites: RwLock::new(HashMap::new()),
+            password_resets: RwLock::new(HashMap::new()),
+            profile_uploads: RwLock::new(HashMap::new()),
             cache: RwLock::new(cache::Cache::default()),
+            locks: lock::Locks::default(),
+            jobs: crate::job::queue::JobQueue::default(),
+            post_events: events::channel(),
         }
     }
 
@@ -51,13 +73,7 @@ impl State {
         }
 
         for stale_post_id in stale_post_ids {
-            match tokio::fs::remove_dir_all(
-                std::path::Path::new(crate::blog::STORE_PATH)
-                    .join("post")
-                    .join(&stale_post_id),
-            )
-            .await
-            {
+            match self.store.delete_post(&stale_post_id).await {
                 Ok(()) => {
                     posts.remove(&stale_post_id);
                 }
@@ -66,3 +82,31 @@ impl State {
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub fn state() -> Arc<State> {
+        Arc::new(State::new(Arc::new(
+            crate::store::memory::MemoryStore::default(),
+        )))
+    }
+
+    pub async fn add_user(state: &State, username: &str) -> crate::blog::User {
+        let user = crate::store::tests::user(username);
+        state.store.put_user(&user).await.unwrap();
+        user
+    }
+
+    pub async fn log_in(state: &State, username: &str) -> crate::blog::SessionID {
+        state
+            .create_session(
+                username.to_owned(),
+                session::Client::default(),
+                crate::auth::Auth::unchecked(),
+            )
+            .await
+            .session
+    }
+}
@@ -0,0 +1,36 @@
+use crate::blog::PasswordResetID;
+
+#[derive(Debug, Clone)]
+pub struct PasswordReset {
+    pub for_username: String,
+    pub expires_at: std::time::Instant,
+}
+
+impl PasswordReset {
+    pub fn is_valid(&self) -> bool {
+        std::time::Instant::now() < self.expires_at
+    }
+}
+
+impl super::State {
+    /// Takes the reset out, so it can only be used once. Expired resets are
+    /// returned too, so check [`PasswordReset::is_valid`].
+    pub async fn remove_password_reset(&self, reset_id: &PasswordResetID) -> Option<PasswordReset> {
+        self.password_resets.write().await.remove(reset_id)
+    }
+
+    pub async fn create_password_reset(&self, for_username: String) -> PasswordResetID {
+        let reset_id: PasswordResetID =
+            crate::blog::get_random_hex_string::<{ crate::blog::PASSWORD_RESET_ID_BYTES }>();
+        let new_password_reset = PasswordReset {
+            for_username,
+            expires_at: std::time::Instant::now() + crate::blog::PASSWORD_RESET_TTL,
+        };
+
+        let mut password_resets = self.password_resets.write().await;
+        password_resets.retain(|_, password_reset| password_reset.is_valid());
+        password_resets.insert(reset_id.clone(), new_password_reset);
+
+        reset_id
+    }
+}
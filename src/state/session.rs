@@ -1,41 +1,341 @@
-use crate::blog::SessionID;
+use crate::blog::{RefreshToken, SessionID};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
 
-#[derive(Debug, Clone)]
+/// Sessions are kept under a hash of their ID, so that reading the store
+/// doesn't hand out sessions that can be used
+pub type SessionHash = String;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
     pub for_username: String,
-    pub expires_at: std::time::Instant,
+    /// when the user logged in, which stays the same across refreshes
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// when the current session ID was swapped in by the refresh token
+    #[serde(default)]
+    pub refreshed_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// pushed back on use, up to [`crate::blog::SESSION_MAX_LIFETIME`] after
+    /// the session ID was made
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    /// only updated every [`crate::blog::SESSION_LAST_USE_PRECISION`], and
+    /// `None` if that hasn't passed since it was created
+    #[serde(default)]
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub client: Client,
+    /// sessions from before refresh tokens don't have one
+    #[serde(default)]
+    pub refresh: Option<Refresh>,
+}
+
+/// Lets an expired session be swapped for a new one. Each refresh token can
+/// only be used once, and comes with a new one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Refresh {
+    pub hash: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// What logging in gives back
+#[derive(Debug, Clone, Serialize)]
+pub struct NewSession {
+    pub session: SessionID,
+    pub refresh_token: RefreshToken,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Where a session was logged in from, so its user can recognize it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Client {
+    pub user_agent: Option<String>,
+    pub ip: Option<std::net::IpAddr>,
 }
 
 impl Session {
     pub fn is_valid(&self) -> bool {
-        std::time::Instant::now() < self.expires_at
+        chrono::Utc::now() < self.expires_at
     }
+    pub fn can_refresh(&self) -> bool {
+        self.refresh
+            .as_ref()
+            .is_some_and(|refresh| chrono::Utc::now() < refresh.expires_at)
+    }
+    /// Neither the session nor its refresh token can be used anymore
+    pub fn is_expired(&self) -> bool {
+        !self.is_valid() && !self.can_refresh()
+    }
+
+    fn max_expires_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.refreshed_at.unwrap_or(self.created_at) + to_chrono(crate::blog::SESSION_MAX_LIFETIME)
+    }
+}
+
+pub fn hash_session_id(session_id: &SessionID) -> SessionHash {
+    hash_token(session_id)
+}
+fn hash_token(token: &str) -> String {
+    crate::blog::to_hex_string(&sha2::Sha256::digest(token.as_bytes()))
+}
+
+fn to_chrono(duration: std::time::Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration)
+        .expect("Constant std::duration be in range of chrono::duration")
 }
 
 impl super::State {
     pub async fn get_session(&self, session_id: &SessionID) -> Option<Session> {
-        let sessions = self.sessions.write().await;
-        let session = sessions.get(session_id)?;
+        let session_hash = hash_session_id(session_id);
+        let mut sessions = self.sessions.write().await;
+        let session = sessions.get_mut(&session_hash)?;
+        if !session.is_valid() {
+            return None;
+        }
+
+        let now = chrono::Utc::now();
+        let last_used_at = session
+            .last_used_at
+            .or(session.refreshed_at)
+            .unwrap_or(session.created_at);
+        if now - last_used_at < to_chrono(crate::blog::SESSION_LAST_USE_PRECISION) {
+            return Some(session.clone());
+        }
+
+        session.last_used_at = Some(now);
+        session.expires_at =
+            (now + to_chrono(crate::blog::SESSION_TTL)).min(session.max_expires_at());
+        let session = session.clone();
+
+        // still holding the lock, so a session removed in the meantime can't be
+        // written back
+        if let Err(err) = self.store.put_session(&session_hash, &session).await {
+            eprintln!(
+                "Error writing session for user {}: {err}",
+                session.for_username
+            );
+        }
+        drop(sessions);
+        Some(session)
+    }
 
-        session.is_valid().then(|| session.clone())
+    /// The user's sessions that can still be used or refreshed, by hash
+    pub async fn sessions_of(&self, username: &str) -> Vec<(SessionHash, Session)> {
+        self.sessions
+            .read()
+            .await
+            .iter()
+            .filter(|(_, session)| session.for_username == username && !session.is_expired())
+            .map(|(hash, session)| (hash.clone(), session.clone()))
+            .collect()
+    }
+
+    /// Logs a session out. Does nothing if it doesn't exist.
+    pub async fn remove_session(&self, session_hash: &SessionHash) -> std::io::Result<()> {
+        // held across the delete so `get_session` can't write it back in between
+        let mut sessions = self.sessions.write().await;
+        self.store.delete_session(session_hash).await?;
+        sessions.remove(session_hash);
+        Ok(())
+    }
+
+    /// Logs the user out everywhere, except for `except` if it's given
+    pub async fn remove_sessions_of(
+        &self,
+        username: &str,
+        except: Option<&SessionHash>,
+    ) -> std::io::Result<()> {
+        for (hash, _) in self.sessions_of(username).await {
+            if Some(&hash) == except {
+                continue;
+            }
+            self.remove_session(&hash).await?;
+        }
+        Ok(())
     }
 
     pub async fn create_session(
         &self,
         for_username: String,
+        client: Client,
         _auth: crate::auth::Auth,
-    ) -> SessionID {
+    ) -> NewSession {
+        let now = chrono::Utc::now();
+        let session = Session {
+            for_username,
+            created_at: now,
+            refreshed_at: None,
+            expires_at: now,
+            last_used_at: None,
+            client,
+            refresh: None,
+        };
+
+        self.insert_session(session).await
+    }
+
+    /// Swaps the session the refresh token belongs to for a new one, with a new
+    /// refresh token. The old session stops working.
+    pub async fn refresh_session(
+        &self,
+        refresh_token: &RefreshToken,
+        client: Client,
+    ) -> Option<NewSession> {
+        let refresh_hash = hash_token(refresh_token);
+        let (old_hash, mut session) = {
+            let mut sessions = self.sessions.write().await;
+            let old_hash = sessions
+                .iter()
+                .find(|(_, session)| {
+                    session
+                        .refresh
+                        .as_ref()
+                        .is_some_and(|refresh| refresh.hash == refresh_hash)
+                })
+                .map(|(hash, _)| hash.clone())?;
+            // removed while the lock is held so the token can't be used twice
+            let session = sessions.remove(&old_hash)?;
+            (old_hash, session)
+        };
+        // an expired session can't be refreshed later either, so it goes too
+        if let Err(err) = self.store.delete_session(&old_hash).await {
+            eprintln!(
+                "Error deleting refreshed session for user {}: {err}",
+                session.for_username
+            );
+        }
+        if !session.can_refresh() {
+            return None;
+        }
+
+        session.refreshed_at = Some(chrono::Utc::now());
+        session.last_used_at = None;
+        session.client = client;
+        Some(self.insert_session(session).await)
+    }
+
+    /// Gives the session a new ID, refresh token and expiry, and saves it
+    async fn insert_session(&self, mut session: Session) -> NewSession {
         let session_id: SessionID =
             crate::blog::get_random_hex_string::<{ crate::blog::SESSION_ID_BYTES }>();
-        let new_session = Session {
-            for_username,
-            expires_at: std::time::Instant::now() + crate::blog::SESSION_TTL,
+        let refresh_token: RefreshToken =
+            crate::blog::get_random_hex_string::<{ crate::blog::REFRESH_TOKEN_BYTES }>();
+        let session_hash = hash_session_id(&session_id);
+        let now = chrono::Utc::now();
+
+        session.expires_at =
+            (now + to_chrono(crate::blog::SESSION_TTL)).min(session.max_expires_at());
+        session.refresh = Some(Refresh {
+            hash: hash_token(&refresh_token),
+            expires_at: now + to_chrono(crate::blog::REFRESH_TOKEN_TTL),
+        });
+        let new_session = NewSession {
+            session: session_id,
+            refresh_token,
+            expires_at: session.expires_at,
         };
 
+        // the session still works until a restart if this fails
+        if let Err(err) = self.store.put_session(&session_hash, &session).await {
+            eprintln!(
+                "Error writing session for user {}: {err}",
+                session.for_username
+            );
+        }
+        self.sessions.write().await.insert(session_hash, session);
+
+        new_session
+    }
+
+    pub async fn load_sessions(&self) -> std::io::Result<()> {
         let mut sessions = self.sessions.write().await;
-        sessions.retain(|_, session| session.is_valid());
-        sessions.insert(session_id.clone(), new_session);
+        for (session_hash, session) in self.store.list_sessions().await? {
+            sessions.insert(session_hash, session);
+        }
+        Ok(())
+    }
+
+    /// Forgets sessions that can't be used or refreshed anymore, both in memory
+    /// and in the store
+    pub async fn cleanup_expired_sessions(&self) {
+        let expired_hashes = {
+            let mut sessions = self.sessions.write().await;
+            let expired_hashes = sessions
+                .iter()
+                .filter(|(_, session)| session.is_expired())
+                .map(|(hash, _)| hash.clone())
+                .collect::<Vec<_>>();
+            for expired_hash in &expired_hashes {
+                sessions.remove(expired_hash);
+            }
+            expired_hashes
+        };
+
+        for expired_hash in expired_hashes {
+            if let Err(err) = self.store.delete_session(&expired_hash).await {
+                eprintln!("Error deleting expired session: {err}");
+            }
+        }
+    }
+
+    pub fn spawn_session_cleanup(self: &std::sync::Arc<Self>) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(crate::blog::SESSION_CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                state.cleanup_expired_sessions().await;
+                state.cleanup_expired_tokens().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn sessions_survive_a_restart() {
+        let store: Arc<dyn crate::store::Store> =
+            Arc::new(crate::store::memory::MemoryStore::default());
+        let state = super::super::State::new(store.clone());
+        let session_id = super::super::tests::log_in(&state, "someone").await;
+        drop(state);
+
+        let state = super::super::State::new(store.clone());
+        state.load_sessions().await.unwrap();
+        let session = state.get_session(&session_id).await.unwrap();
+        assert_eq!(session.for_username, "someone");
+
+        let stored = store.list_sessions().await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].0, hash_session_id(&session_id));
+        assert!(!serde_json::to_string(&stored[0].1)
+            .unwrap()
+            .contains(&session_id));
+    }
+
+    #[tokio::test]
+    async fn expired_refreshes_delete_the_session() {
+        let store: Arc<dyn crate::store::Store> =
+            Arc::new(crate::store::memory::MemoryStore::default());
+        let state = super::super::State::new(store.clone());
+        let new_session = state
+            .create_session(
+                "someone".to_owned(),
+                Client::default(),
+                crate::auth::Auth::unchecked(),
+            )
+            .await;
+        for session in state.sessions.write().await.values_mut() {
+            session.refresh.as_mut().unwrap().expires_at = chrono::Utc::now();
+        }
 
-        session_id
+        assert!(state
+            .refresh_session(&new_session.refresh_token, Client::default())
+            .await
+            .is_none());
+        assert!(store.list_sessions().await.unwrap().is_empty());
+        assert!(state.sessions.read().await.is_empty());
     }
 }
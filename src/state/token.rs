@@ -0,0 +1,171 @@
+use crate::blog::{ApiToken, SessionID};
+use serde::{Deserialize, Serialize};
+
+/// Like sessions, tokens are kept under a hash so reading the store doesn't
+/// hand out tokens that can be used
+pub type TokenHash = String;
+
+/// What an API token is allowed to do. Sessions from logging in can do all of
+/// these and more, like managing the account itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// see posts and members only the user can see
+    ReadPrivate,
+    /// create posts and edit the user's own
+    CreatePost,
+    DeletePost,
+    /// members, membership requests and circles
+    ManageMembers,
+}
+
+/// A long-lived personal access token, for scripts and bots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    pub for_username: String,
+    /// what the user called it, so they can tell their tokens apart
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// `None` if it lasts until it's revoked
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// only updated every [`crate::blog::SESSION_LAST_USE_PRECISION`]
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Token {
+    pub fn is_valid(&self) -> bool {
+        self.expires_at
+            .is_none_or(|expires_at| chrono::Utc::now() < expires_at)
+    }
+}
+
+pub fn hash_token(token: &ApiToken) -> TokenHash {
+    super::session::hash_session_id(token)
+}
+
+impl super::State {
+    /// The user a session or API token belongs to, if it's allowed to do
+    /// `scope`. Sessions from logging in are allowed to do anything.
+    pub async fn authorize(&self, session_id: &SessionID, scope: Scope) -> Option<String> {
+        if let Some(session) = self.get_session(session_id).await {
+            return Some(session.for_username);
+        }
+
+        let token = self.get_token(session_id).await?;
+        token.scopes.contains(&scope).then_some(token.for_username)
+    }
+
+    async fn get_token(&self, token: &ApiToken) -> Option<Token> {
+        let token_hash = hash_token(token);
+        let mut tokens = self.tokens.write().await;
+        let token = tokens.get_mut(&token_hash)?;
+        if !token.is_valid() {
+            return None;
+        }
+
+        let now = chrono::Utc::now();
+        let last_used_at = token.last_used_at.unwrap_or(token.created_at);
+        if now - last_used_at
+            < chrono::Duration::from_std(crate::blog::SESSION_LAST_USE_PRECISION)
+                .expect("Constant std::duration be in range of chrono::duration")
+        {
+            return Some(token.clone());
+        }
+
+        token.last_used_at = Some(now);
+        let token = token.clone();
+
+        // still holding the lock, so a token revoked in the meantime can't be
+        // written back
+        if let Err(err) = self.store.put_token(&token_hash, &token).await {
+            eprintln!("Error writing token for user {}: {err}", token.for_username);
+        }
+        drop(tokens);
+        Some(token)
+    }
+
+    /// The user's tokens that haven't expired, by hash
+    pub async fn tokens_of(&self, username: &str) -> Vec<(TokenHash, Token)> {
+        self.tokens
+            .read()
+            .await
+            .iter()
+            .filter(|(_, token)| token.for_username == username && token.is_valid())
+            .map(|(hash, token)| (hash.clone(), token.clone()))
+            .collect()
+    }
+
+    pub async fn create_token(
+        &self,
+        for_username: String,
+        name: String,
+        scopes: Vec<Scope>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> std::io::Result<(ApiToken, TokenHash)> {
+        let api_token: ApiToken =
+            crate::blog::get_random_hex_string::<{ crate::blog::API_TOKEN_BYTES }>();
+        let token_hash = hash_token(&api_token);
+        let token = Token {
+            for_username,
+            name,
+            scopes,
+            created_at: chrono::Utc::now(),
+            expires_at,
+            last_used_at: None,
+        };
+
+        // unlike a session, a token that would be gone after a restart is no use
+        self.store.put_token(&token_hash, &token).await?;
+        self.tokens.write().await.insert(token_hash.clone(), token);
+
+        Ok((api_token, token_hash))
+    }
+
+    /// Does nothing if the token doesn't exist
+    pub async fn remove_token(&self, token_hash: &TokenHash) -> std::io::Result<()> {
+        // held across the delete so `get_token` can't write it back in between
+        let mut tokens = self.tokens.write().await;
+        self.store.delete_token(token_hash).await?;
+        tokens.remove(token_hash);
+        Ok(())
+    }
+
+    /// Revokes all of the user's tokens
+    pub async fn remove_tokens_of(&self, username: &str) -> std::io::Result<()> {
+        for (hash, _) in self.tokens_of(username).await {
+            self.remove_token(&hash).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn load_tokens(&self) -> std::io::Result<()> {
+        let mut tokens = self.tokens.write().await;
+        for (token_hash, token) in self.store.list_tokens().await? {
+            tokens.insert(token_hash, token);
+        }
+        Ok(())
+    }
+
+    /// Forgets tokens that have expired, both in memory and in the store
+    pub async fn cleanup_expired_tokens(&self) {
+        let expired_hashes = {
+            let mut tokens = self.tokens.write().await;
+            let expired_hashes = tokens
+                .iter()
+                .filter(|(_, token)| !token.is_valid())
+                .map(|(hash, _)| hash.clone())
+                .collect::<Vec<_>>();
+            for expired_hash in &expired_hashes {
+                tokens.remove(expired_hash);
+            }
+            expired_hashes
+        };
+
+        for expired_hash in expired_hashes {
+            if let Err(err) = self.store.delete_token(&expired_hash).await {
+                eprintln!("Error deleting expired token: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::blog::{ProfileImage, UploadID};
+
+/// A profile image that's been started but whose socket hasn't connected yet
+#[derive(Debug, Clone)]
+pub struct ProfileUpload {
+    pub for_username: String,
+    pub image: ProfileImage,
+    /// what the raw image will be written to
+    pub name: String,
+    pub expires_at: std::time::Instant,
+}
+
+impl ProfileUpload {
+    pub fn is_valid(&self) -> bool {
+        std::time::Instant::now() < self.expires_at
+    }
+}
+
+impl super::State {
+    /// Each upload can only be connected to once
+    pub async fn take_profile_upload(&self, upload_id: &UploadID) -> Option<ProfileUpload> {
+        let upload = self.profile_uploads.write().await.remove(upload_id)?;
+
+        upload.is_valid().then_some(upload)
+    }
+
+    pub async fn create_profile_upload(
+        &self,
+        for_username: String,
+        image: ProfileImage,
+        name: String,
+    ) -> UploadID {
+        let upload_id: UploadID =
+            crate::blog::get_random_hex_string::<{ crate::blog::UPLOAD_ID_BYTES }>();
+        let new_upload = ProfileUpload {
+            for_username,
+            image,
+            name,
+            expires_at: std::time::Instant::now() + crate::blog::PROFILE_UPLOAD_TTL,
+        };
+
+        let mut uploads = self.profile_uploads.write().await;
+        uploads.retain(|_, upload| upload.is_valid());
+        uploads.insert(upload_id.clone(), new_upload);
+
+        upload_id
+    }
+}
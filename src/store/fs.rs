@@ -0,0 +1,730 @@
+use super::{MediaReader, MediaSize, Store};
+use crate::auth::Credential;
+use crate::blog::{Post, PostID, Revision, User};
+use crate::job::queue::{QueuedJob, QueuedJobID};
+use crate::state::incomplete::IncompletePost;
+use crate::state::session::{Session, SessionHash};
+use crate::state::token::{Token, TokenHash};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+/// The original directory layout:
+///
+/// ```text
+/// credential/<username>.json
+/// user/<username>.json
+/// user/<username>/{raw,small,large}/<name>
+/// post/<id>/meta.json
+/// post/<id>/incomplete.json
+/// post/<id>/text.md
+/// post/<id>/revision/<number>.json
+/// post/<id>/image/{raw,small,large}/<name>
+/// job/<id>.json
+/// job/dead/<id>.json
+/// session/<hash>.json
+/// token/<hash>.json
+/// ```
+#[derive(Debug)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new(root: impl Into<PathBuf>) -> FsStore {
+        FsStore { root: root.into() }
+    }
+
+    /// Moves every login from the old `logins.txt` into the credential store,
+    /// then renames it so it's only done once. Users who already have a
+    /// credential keep it.
+    pub async fn migrate_logins(&self) -> std::io::Result<()> {
+        let logins_path = self.root.join("logins.txt");
+        let file = match tokio::fs::File::open(&logins_path).await {
+            Ok(it) => it,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let mut reader = tokio::io::BufReader::new(file).lines();
+
+        // later lines win, since passwords used to be changed by appending
+        let mut logins = HashMap::new();
+        while let Some(line) = reader.next_line().await? {
+            let Some((username, hash)) = line.split_once('\t') else {
+                continue;
+            };
+            logins.insert(username.to_owned(), hash.to_owned());
+        }
+
+        let now = chrono::Utc::now();
+        for (username, hash) in &logins {
+            let credential = Credential {
+                hash: hash.clone(),
+                created_at: now,
+                updated_at: now,
+                failed_attempts: 0,
+                last_failed_at: None,
+                totp: None,
+            };
+            self.insert_credential(username, &credential).await?;
+        }
+
+        tokio::fs::rename(&logins_path, self.root.join("logins.txt.migrated")).await?;
+        println!("Migrated {} logins from {logins_path:?}", logins.len());
+        Ok(())
+    }
+
+    fn post_path(&self, post_id: &PostID) -> std::io::Result<PathBuf> {
+        Ok(self.root.join("post").join(check_name(post_id)?))
+    }
+    fn revision_path(&self, post_id: &PostID, number: u32) -> std::io::Result<PathBuf> {
+        Ok(self
+            .post_path(post_id)?
+            .join("revision")
+            .join(format!("{number}.json")))
+    }
+    fn user_path(&self, username: &str) -> std::io::Result<PathBuf> {
+        Ok(self
+            .root
+            .join("user")
+            .join(format!("{}.json", check_name(username)?)))
+    }
+    fn job_path(&self, job_id: &QueuedJobID) -> PathBuf {
+        self.root.join("job").join(format!("{job_id}.json"))
+    }
+    fn dead_job_path(&self, job_id: &QueuedJobID) -> PathBuf {
+        self.root
+            .join("job")
+            .join("dead")
+            .join(format!("{job_id}.json"))
+    }
+    fn user_media_folder(&self, username: &str, size: MediaSize) -> std::io::Result<PathBuf> {
+        Ok(self
+            .root
+            .join("user")
+            .join(check_name(username)?)
+            .join(size.folder_name()))
+    }
+    fn user_media_path(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<PathBuf> {
+        Ok(self
+            .user_media_folder(username, size)?
+            .join(check_name(name)?))
+    }
+    fn credential_path(&self, username: &str) -> std::io::Result<PathBuf> {
+        Ok(self
+            .root
+            .join("credential")
+            .join(format!("{}.json", check_name(username)?)))
+    }
+    fn session_path(&self, hash: &SessionHash) -> PathBuf {
+        self.root.join("session").join(format!("{hash}.json"))
+    }
+    fn token_path(&self, hash: &TokenHash) -> PathBuf {
+        self.root.join("token").join(format!("{hash}.json"))
+    }
+    fn media_folder(&self, post_id: &PostID, size: MediaSize) -> std::io::Result<PathBuf> {
+        Ok(self
+            .post_path(post_id)?
+            .join("image")
+            .join(size.folder_name()))
+    }
+    fn media_path(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<PathBuf> {
+        Ok(self.media_folder(post_id, size)?.join(check_name(name)?))
+    }
+}
+
+/// Names from callers end up as a single path component, so anything that
+/// could climb out of its folder is refused
+fn check_name(name: &str) -> std::io::Result<&str> {
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name.contains("..") {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{name:?} is not a valid name"),
+        ));
+    }
+    Ok(name)
+}
+
+/// Reads one file of a listing. A file that can't be read or parsed is logged
+/// and left out, so one torn write doesn't fail the whole listing.
+async fn read_listed<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let item = match tokio::fs::read(path).await {
+        Ok(file) => serde_json::from_slice(&file).map_err(std::io::Error::from),
+        Err(err) => Err(err),
+    };
+    match item {
+        Ok(it) => Some(it),
+        Err(err) => {
+            eprintln!("Error reading {path:?}, skipping it: {err}");
+            None
+        }
+    }
+}
+
+async fn read_optional(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(it) => Ok(Some(it)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Writes and syncs a temporary file next to `path`, returning where it is
+async fn write_temp(path: &Path, data: &[u8]) -> std::io::Result<PathBuf> {
+    let Some(file_name) = path.file_name() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{path:?} is not a file path"),
+        ));
+    };
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        crate::blog::get_random_hex_string::<8>()
+    ));
+
+    let result = async {
+        let mut temp_file = tokio::fs::File::create(&temp_path).await?;
+        temp_file.write_all(data).await?;
+        temp_file.sync_all().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok(temp_path),
+        Err(err) => {
+            _ = tokio::fs::remove_file(&temp_path).await;
+            Err(err)
+        }
+    }
+}
+
+/// The rename or link into a directory only becomes durable once it's synced
+async fn sync_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => tokio::fs::File::open(parent).await?.sync_all().await,
+        None => Ok(()),
+    }
+}
+
+/// Writes to a temporary file next to `path` and renames it over `path` once
+/// it's synced, so a crash mid-write leaves either the old or the new contents
+/// and never a truncated file.
+async fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp_path = write_temp(path, data).await?;
+
+    let result = async {
+        tokio::fs::rename(&temp_path, path).await?;
+        sync_parent(path).await
+    }
+    .await;
+
+    if result.is_err() {
+        _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// Like [`write_atomic`], but only if nothing is at `path` yet. Linking fails
+/// when the target exists, so two writers can't both succeed. Returns whether
+/// it wrote the file.
+async fn write_new_atomic(path: &Path, data: &[u8]) -> std::io::Result<bool> {
+    let temp_path = write_temp(path, data).await?;
+
+    let result = match tokio::fs::hard_link(&temp_path, path).await {
+        Ok(()) => sync_parent(path).await.map(|()| true),
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err),
+    };
+
+    _ = tokio::fs::remove_file(&temp_path).await;
+    result
+}
+
+/// Tokio files write in the background, so the data is only there once it's
+/// been flushed
+async fn append(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .await?;
+    file.write_all(data).await?;
+    file.flush().await
+}
+
+/// Leftovers from a [`write_atomic`] that never got renamed
+fn is_temp_file(file_name: &std::ffi::OsStr) -> bool {
+    let file_name = file_name.to_string_lossy();
+    file_name.starts_with('.') && file_name.ends_with(".tmp")
+}
+
+async fn read_jobs(jobs_path: &Path) -> std::io::Result<Vec<QueuedJob>> {
+    let mut jobs_dir = match tokio::fs::read_dir(jobs_path).await {
+        Ok(it) => it,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut jobs = Vec::new();
+
+    while let Some(entry) = jobs_dir.next_entry().await? {
+        if is_temp_file(&entry.file_name()) || entry.file_type().await?.is_dir() {
+            continue;
+        }
+        if let Some(job) = read_listed(&entry.path()).await {
+            jobs.push(job);
+        }
+    }
+
+    Ok(jobs)
+}
+
+async fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => tokio::fs::create_dir_all(parent).await,
+        None => Ok(()),
+    }
+}
+
+#[async_trait::async_trait]
+impl super::Store for FsStore {
+    async fn get_post(&self, post_id: &PostID) -> std::io::Result<Option<Post>> {
+        let Some(file) = read_optional(&self.post_path(post_id)?.join("meta.json")).await? else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&file)?))
+    }
+    async fn put_post(&self, post: &Post) -> std::io::Result<()> {
+        let post_path = self.post_path(&post.id)?;
+        tokio::fs::create_dir_all(&post_path).await?;
+        write_atomic(
+            &post_path.join("meta.json"),
+            &serde_json::to_vec(post).expect("post meta should serialize"),
+        )
+        .await
+    }
+    async fn delete_post(&self, post_id: &PostID) -> std::io::Result<()> {
+        match tokio::fs::remove_dir_all(self.post_path(post_id)?).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+    async fn list_posts(&self) -> std::io::Result<Vec<PostID>> {
+        let mut posts_dir = tokio::fs::read_dir(self.root.join("post")).await?;
+        let mut post_ids = Vec::new();
+
+        while let Some(entry) = posts_dir.next_entry().await? {
+            post_ids.push(entry.file_name().to_string_lossy().into_owned());
+        }
+
+        Ok(post_ids)
+    }
+
+    async fn get_incomplete(&self, post_id: &PostID) -> std::io::Result<Option<IncompletePost>> {
+        let Some(file) = read_optional(&self.post_path(post_id)?.join("incomplete.json")).await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&file)?))
+    }
+    async fn put_incomplete(&self, post: &IncompletePost) -> std::io::Result<()> {
+        write_atomic(
+            &self.post_path(&post.meta.id)?.join("incomplete.json"),
+            &serde_json::to_vec(post).expect("incomplete post should serialize"),
+        )
+        .await
+    }
+    async fn delete_incomplete(&self, post_id: &PostID) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.post_path(post_id)?.join("incomplete.json")).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_text(&self, post_id: &PostID) -> std::io::Result<Option<String>> {
+        let Some(file) = read_optional(&self.post_path(post_id)?.join("text.md")).await? else {
+            return Ok(None);
+        };
+        String::from_utf8(file)
+            .map(Some)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+    async fn put_text(&self, post_id: &PostID, text: &str) -> std::io::Result<()> {
+        write_atomic(&self.post_path(post_id)?.join("text.md"), text.as_bytes()).await
+    }
+
+    async fn list_revisions(&self, post_id: &PostID) -> std::io::Result<Vec<Revision>> {
+        let mut revisions_dir =
+            match tokio::fs::read_dir(self.post_path(post_id)?.join("revision")).await {
+                Ok(it) => it,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(err) => return Err(err),
+            };
+        let mut revisions: Vec<Revision> = Vec::new();
+
+        while let Some(entry) = revisions_dir.next_entry().await? {
+            if is_temp_file(&entry.file_name()) {
+                continue;
+            }
+            if let Some(revision) = read_listed(&entry.path()).await {
+                revisions.push(revision);
+            }
+        }
+
+        revisions.sort_by_key(|revision| revision.number);
+        Ok(revisions)
+    }
+    async fn get_revision(
+        &self,
+        post_id: &PostID,
+        number: u32,
+    ) -> std::io::Result<Option<Revision>> {
+        let Some(file) = read_optional(&self.revision_path(post_id, number)?).await? else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&file)?))
+    }
+    async fn put_revision(&self, post_id: &PostID, revision: &Revision) -> std::io::Result<()> {
+        let revision_path = self.revision_path(post_id, revision.number)?;
+        create_parent(&revision_path).await?;
+        write_atomic(
+            &revision_path,
+            &serde_json::to_vec(revision).expect("revision should serialize"),
+        )
+        .await
+    }
+
+    async fn get_user(&self, username: &str) -> std::io::Result<Option<User>> {
+        let Some(file) = read_optional(&self.user_path(username)?).await? else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&file)?))
+    }
+    async fn put_user(&self, user: &User) -> std::io::Result<()> {
+        write_atomic(
+            &self.user_path(&user.username)?,
+            &serde_json::to_vec(user).expect("user should serialize"),
+        )
+        .await
+    }
+    async fn list_users(&self) -> std::io::Result<Vec<User>> {
+        let mut users_dir = tokio::fs::read_dir(self.root.join("user")).await?;
+        let mut users = Vec::new();
+
+        while let Some(entry) = users_dir.next_entry().await? {
+            // user/<username>/ holds their media
+            if is_temp_file(&entry.file_name()) || entry.file_type().await?.is_dir() {
+                continue;
+            }
+            if let Some(user) = read_listed(&entry.path()).await {
+                users.push(user);
+            }
+        }
+
+        Ok(users)
+    }
+
+    async fn open_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<MediaReader>> {
+        match tokio::fs::File::open(self.media_path(post_id, size, name)?).await {
+            Ok(file) => Ok(Some(Box::new(file))),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+    async fn get_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        read_optional(&self.media_path(post_id, size, name)?).await
+    }
+    async fn put_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let media_path = self.media_path(post_id, size, name)?;
+        create_parent(&media_path).await?;
+        write_atomic(&media_path, data).await
+    }
+    async fn append_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let media_path = self.media_path(post_id, size, name)?;
+        create_parent(&media_path).await?;
+        append(&media_path, data).await
+    }
+    async fn delete_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()> {
+        tokio::fs::remove_file(self.media_path(post_id, size, name)?).await
+    }
+
+    async fn list_media(&self, post_id: &PostID) -> std::io::Result<Vec<(MediaSize, String)>> {
+        let mut media = Vec::new();
+
+        for size in enum_iterator::all::<MediaSize>() {
+            let size_path = self.media_folder(post_id, size)?;
+            let mut size_dir = match tokio::fs::read_dir(size_path).await {
+                Ok(it) => it,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+
+            while let Some(entry) = size_dir.next_entry().await? {
+                if is_temp_file(&entry.file_name()) {
+                    continue;
+                }
+                media.push((size, entry.file_name().to_string_lossy().into_owned()));
+            }
+        }
+
+        Ok(media)
+    }
+
+    async fn get_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        read_optional(&self.user_media_path(username, size, name)?).await
+    }
+    async fn put_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let media_path = self.user_media_path(username, size, name)?;
+        create_parent(&media_path).await?;
+        write_atomic(&media_path, data).await
+    }
+    async fn append_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let media_path = self.user_media_path(username, size, name)?;
+        create_parent(&media_path).await?;
+        append(&media_path, data).await
+    }
+    async fn delete_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()> {
+        tokio::fs::remove_file(self.user_media_path(username, size, name)?).await
+    }
+    async fn list_user_media(&self, username: &str) -> std::io::Result<Vec<(MediaSize, String)>> {
+        let mut media = Vec::new();
+
+        for size in enum_iterator::all::<MediaSize>() {
+            let size_path = self.user_media_folder(username, size)?;
+            let mut size_dir = match tokio::fs::read_dir(size_path).await {
+                Ok(it) => it,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+
+            while let Some(entry) = size_dir.next_entry().await? {
+                if is_temp_file(&entry.file_name()) {
+                    continue;
+                }
+                media.push((size, entry.file_name().to_string_lossy().into_owned()));
+            }
+        }
+
+        Ok(media)
+    }
+
+    async fn list_jobs(&self) -> std::io::Result<Vec<QueuedJob>> {
+        read_jobs(&self.root.join("job")).await
+    }
+    async fn put_job(&self, job: &QueuedJob) -> std::io::Result<()> {
+        let job_path = self.job_path(&job.id);
+        create_parent(&job_path).await?;
+        write_atomic(
+            &job_path,
+            &serde_json::to_vec(job).expect("job should serialize"),
+        )
+        .await
+    }
+    async fn delete_job(&self, job_id: &QueuedJobID) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.job_path(job_id)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+    async fn list_dead_jobs(&self) -> std::io::Result<Vec<QueuedJob>> {
+        read_jobs(&self.root.join("job").join("dead")).await
+    }
+    async fn put_dead_job(&self, job: &QueuedJob) -> std::io::Result<()> {
+        let job_path = self.dead_job_path(&job.id);
+        create_parent(&job_path).await?;
+        write_atomic(
+            &job_path,
+            &serde_json::to_vec(job).expect("job should serialize"),
+        )
+        .await
+    }
+
+    async fn list_sessions(&self) -> std::io::Result<Vec<(SessionHash, Session)>> {
+        let mut sessions_dir = match tokio::fs::read_dir(self.root.join("session")).await {
+            Ok(it) => it,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut sessions = Vec::new();
+
+        while let Some(entry) = sessions_dir.next_entry().await? {
+            let path = entry.path();
+            if is_temp_file(&entry.file_name()) {
+                continue;
+            }
+            let Some(hash) = path.file_stem() else {
+                continue;
+            };
+            if let Some(session) = read_listed(&path).await {
+                sessions.push((hash.to_string_lossy().into_owned(), session));
+            }
+        }
+
+        Ok(sessions)
+    }
+    async fn put_session(&self, hash: &SessionHash, session: &Session) -> std::io::Result<()> {
+        let session_path = self.session_path(hash);
+        create_parent(&session_path).await?;
+        write_atomic(
+            &session_path,
+            &serde_json::to_vec(session).expect("session should serialize"),
+        )
+        .await
+    }
+    async fn delete_session(&self, hash: &SessionHash) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.session_path(hash)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list_tokens(&self) -> std::io::Result<Vec<(TokenHash, Token)>> {
+        let mut tokens_dir = match tokio::fs::read_dir(self.root.join("token")).await {
+            Ok(it) => it,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut tokens = Vec::new();
+
+        while let Some(entry) = tokens_dir.next_entry().await? {
+            let path = entry.path();
+            if is_temp_file(&entry.file_name()) {
+                continue;
+            }
+            let Some(hash) = path.file_stem() else {
+                continue;
+            };
+            if let Some(token) = read_listed(&path).await {
+                tokens.push((hash.to_string_lossy().into_owned(), token));
+            }
+        }
+
+        Ok(tokens)
+    }
+    async fn put_token(&self, hash: &TokenHash, token: &Token) -> std::io::Result<()> {
+        let token_path = self.token_path(hash);
+        create_parent(&token_path).await?;
+        write_atomic(
+            &token_path,
+            &serde_json::to_vec(token).expect("token should serialize"),
+        )
+        .await
+    }
+    async fn delete_token(&self, hash: &TokenHash) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.token_path(hash)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_credential(&self, username: &str) -> std::io::Result<Option<Credential>> {
+        let Some(file) = read_optional(&self.credential_path(username)?).await? else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_slice(&file)?))
+    }
+    async fn insert_credential(
+        &self,
+        username: &str,
+        credential: &Credential,
+    ) -> std::io::Result<bool> {
+        let credential_path = self.credential_path(username)?;
+        create_parent(&credential_path).await?;
+        write_new_atomic(
+            &credential_path,
+            &serde_json::to_vec(credential).expect("credential should serialize"),
+        )
+        .await
+    }
+    async fn put_credential(&self, username: &str, credential: &Credential) -> std::io::Result<()> {
+        let credential_path = self.credential_path(username)?;
+        create_parent(&credential_path).await?;
+        write_atomic(
+            &credential_path,
+            &serde_json::to_vec(credential).expect("credential should serialize"),
+        )
+        .await
+    }
+    async fn list_credentials(&self) -> std::io::Result<Vec<(String, Credential)>> {
+        let mut credentials_dir = match tokio::fs::read_dir(self.root.join("credential")).await {
+            Ok(it) => it,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut credentials = Vec::new();
+
+        while let Some(entry) = credentials_dir.next_entry().await? {
+            let path = entry.path();
+            if is_temp_file(&entry.file_name()) {
+                continue;
+            }
+            let Some(username) = path.file_stem() else {
+                continue;
+            };
+            if let Some(credential) = read_listed(&path).await {
+                credentials.push((username.to_string_lossy().into_owned(), credential));
+            }
+        }
+
+        Ok(credentials)
+    }
+}
@@ -0,0 +1,376 @@
+use super::{MediaReader, MediaSize};
+use crate::auth::Credential;
+use crate::blog::{Post, PostID, Revision, User};
+use crate::job::queue::{QueuedJob, QueuedJobID};
+use crate::state::incomplete::IncompletePost;
+use crate::state::session::{Session, SessionHash};
+use crate::state::token::{Token, TokenHash};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+type MediaKey = (PostID, MediaSize, String);
+type UserMediaKey = (String, MediaSize, String);
+
+/// Keeps everything in memory and forgets it on shutdown. Useful for tests and
+/// for running the server without a store folder.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    posts: RwLock<HashMap<PostID, Post>>,
+    incomplete_posts: RwLock<HashMap<PostID, IncompletePost>>,
+    texts: RwLock<HashMap<PostID, String>>,
+    revisions: RwLock<HashMap<PostID, Vec<Revision>>>,
+    users: RwLock<HashMap<String, User>>,
+    media: RwLock<HashMap<MediaKey, Vec<u8>>>,
+    user_media: RwLock<HashMap<UserMediaKey, Vec<u8>>>,
+    jobs: RwLock<HashMap<QueuedJobID, QueuedJob>>,
+    dead_jobs: RwLock<HashMap<QueuedJobID, QueuedJob>>,
+    sessions: RwLock<HashMap<SessionHash, Session>>,
+    tokens: RwLock<HashMap<TokenHash, Token>>,
+    credentials: RwLock<HashMap<String, Credential>>,
+}
+
+fn media_key(post_id: &PostID, size: MediaSize, name: &str) -> MediaKey {
+    (post_id.clone(), size, name.to_owned())
+}
+fn user_media_key(username: &str, size: MediaSize, name: &str) -> UserMediaKey {
+    (username.to_owned(), size, name.to_owned())
+}
+
+#[async_trait::async_trait]
+impl super::Store for MemoryStore {
+    async fn get_post(&self, post_id: &PostID) -> std::io::Result<Option<Post>> {
+        Ok(self.posts.read().await.get(post_id).cloned())
+    }
+    async fn put_post(&self, post: &Post) -> std::io::Result<()> {
+        self.posts
+            .write()
+            .await
+            .insert(post.id.clone(), post.clone());
+        Ok(())
+    }
+    async fn delete_post(&self, post_id: &PostID) -> std::io::Result<()> {
+        self.posts.write().await.remove(post_id);
+        self.incomplete_posts.write().await.remove(post_id);
+        self.texts.write().await.remove(post_id);
+        self.revisions.write().await.remove(post_id);
+        self.media
+            .write()
+            .await
+            .retain(|(media_post_id, _, _), _| media_post_id != post_id);
+        Ok(())
+    }
+    async fn list_posts(&self) -> std::io::Result<Vec<PostID>> {
+        Ok(self.posts.read().await.keys().cloned().collect())
+    }
+
+    async fn get_incomplete(&self, post_id: &PostID) -> std::io::Result<Option<IncompletePost>> {
+        Ok(self.incomplete_posts.read().await.get(post_id).cloned())
+    }
+    async fn put_incomplete(&self, post: &IncompletePost) -> std::io::Result<()> {
+        self.incomplete_posts
+            .write()
+            .await
+            .insert(post.meta.id.clone(), post.clone());
+        Ok(())
+    }
+    async fn delete_incomplete(&self, post_id: &PostID) -> std::io::Result<()> {
+        self.incomplete_posts.write().await.remove(post_id);
+        Ok(())
+    }
+
+    async fn get_text(&self, post_id: &PostID) -> std::io::Result<Option<String>> {
+        Ok(self.texts.read().await.get(post_id).cloned())
+    }
+    async fn put_text(&self, post_id: &PostID, text: &str) -> std::io::Result<()> {
+        self.texts
+            .write()
+            .await
+            .insert(post_id.clone(), text.to_owned());
+        Ok(())
+    }
+
+    async fn list_revisions(&self, post_id: &PostID) -> std::io::Result<Vec<Revision>> {
+        Ok(self
+            .revisions
+            .read()
+            .await
+            .get(post_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+    async fn get_revision(
+        &self,
+        post_id: &PostID,
+        number: u32,
+    ) -> std::io::Result<Option<Revision>> {
+        Ok(self
+            .revisions
+            .read()
+            .await
+            .get(post_id)
+            .and_then(|revisions| {
+                revisions
+                    .iter()
+                    .find(|revision| revision.number == number)
+                    .cloned()
+            }))
+    }
+    async fn put_revision(&self, post_id: &PostID, revision: &Revision) -> std::io::Result<()> {
+        let mut revisions = self.revisions.write().await;
+        let revisions = revisions.entry(post_id.clone()).or_default();
+
+        revisions.retain(|existing| existing.number != revision.number);
+        revisions.push(revision.clone());
+        revisions.sort_by_key(|revision| revision.number);
+        Ok(())
+    }
+
+    async fn get_user(&self, username: &str) -> std::io::Result<Option<User>> {
+        Ok(self.users.read().await.get(username).cloned())
+    }
+    async fn put_user(&self, user: &User) -> std::io::Result<()> {
+        self.users
+            .write()
+            .await
+            .insert(user.username.clone(), user.clone());
+        Ok(())
+    }
+    async fn list_users(&self) -> std::io::Result<Vec<User>> {
+        Ok(self.users.read().await.values().cloned().collect())
+    }
+
+    async fn open_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<MediaReader>> {
+        let media = self.get_media(post_id, size, name).await?;
+        Ok(media.map(|data| Box::new(std::io::Cursor::new(data)) as MediaReader))
+    }
+    async fn get_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self
+            .media
+            .read()
+            .await
+            .get(&media_key(post_id, size, name))
+            .cloned())
+    }
+    async fn put_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.media
+            .write()
+            .await
+            .insert(media_key(post_id, size, name), data.to_vec());
+        Ok(())
+    }
+    async fn append_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.media
+            .write()
+            .await
+            .entry(media_key(post_id, size, name))
+            .or_default()
+            .extend_from_slice(data);
+        Ok(())
+    }
+    async fn delete_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()> {
+        self.media
+            .write()
+            .await
+            .remove(&media_key(post_id, size, name));
+        Ok(())
+    }
+
+    async fn list_media(&self, post_id: &PostID) -> std::io::Result<Vec<(MediaSize, String)>> {
+        Ok(self
+            .media
+            .read()
+            .await
+            .keys()
+            .filter(|(media_post_id, _, _)| media_post_id == post_id)
+            .map(|(_, size, name)| (*size, name.clone()))
+            .collect())
+    }
+
+    async fn get_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self
+            .user_media
+            .read()
+            .await
+            .get(&user_media_key(username, size, name))
+            .cloned())
+    }
+    async fn put_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.user_media
+            .write()
+            .await
+            .insert(user_media_key(username, size, name), data.to_vec());
+        Ok(())
+    }
+    async fn append_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.user_media
+            .write()
+            .await
+            .entry(user_media_key(username, size, name))
+            .or_default()
+            .extend_from_slice(data);
+        Ok(())
+    }
+    async fn delete_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()> {
+        self.user_media
+            .write()
+            .await
+            .remove(&user_media_key(username, size, name));
+        Ok(())
+    }
+    async fn list_user_media(&self, username: &str) -> std::io::Result<Vec<(MediaSize, String)>> {
+        Ok(self
+            .user_media
+            .read()
+            .await
+            .keys()
+            .filter(|(media_username, _, _)| media_username == username)
+            .map(|(_, size, name)| (*size, name.clone()))
+            .collect())
+    }
+
+    async fn list_jobs(&self) -> std::io::Result<Vec<QueuedJob>> {
+        Ok(self.jobs.read().await.values().cloned().collect())
+    }
+    async fn put_job(&self, job: &QueuedJob) -> std::io::Result<()> {
+        self.jobs.write().await.insert(job.id.clone(), job.clone());
+        Ok(())
+    }
+    async fn delete_job(&self, job_id: &QueuedJobID) -> std::io::Result<()> {
+        self.jobs.write().await.remove(job_id);
+        Ok(())
+    }
+    async fn list_dead_jobs(&self) -> std::io::Result<Vec<QueuedJob>> {
+        Ok(self.dead_jobs.read().await.values().cloned().collect())
+    }
+    async fn put_dead_job(&self, job: &QueuedJob) -> std::io::Result<()> {
+        self.dead_jobs
+            .write()
+            .await
+            .insert(job.id.clone(), job.clone());
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> std::io::Result<Vec<(SessionHash, Session)>> {
+        Ok(self
+            .sessions
+            .read()
+            .await
+            .iter()
+            .map(|(hash, session)| (hash.clone(), session.clone()))
+            .collect())
+    }
+    async fn put_session(&self, hash: &SessionHash, session: &Session) -> std::io::Result<()> {
+        self.sessions
+            .write()
+            .await
+            .insert(hash.clone(), session.clone());
+        Ok(())
+    }
+    async fn delete_session(&self, hash: &SessionHash) -> std::io::Result<()> {
+        self.sessions.write().await.remove(hash);
+        Ok(())
+    }
+
+    async fn list_tokens(&self) -> std::io::Result<Vec<(TokenHash, Token)>> {
+        Ok(self
+            .tokens
+            .read()
+            .await
+            .iter()
+            .map(|(hash, token)| (hash.clone(), token.clone()))
+            .collect())
+    }
+    async fn put_token(&self, hash: &TokenHash, token: &Token) -> std::io::Result<()> {
+        self.tokens
+            .write()
+            .await
+            .insert(hash.clone(), token.clone());
+        Ok(())
+    }
+    async fn delete_token(&self, hash: &TokenHash) -> std::io::Result<()> {
+        self.tokens.write().await.remove(hash);
+        Ok(())
+    }
+
+    async fn get_credential(&self, username: &str) -> std::io::Result<Option<Credential>> {
+        Ok(self.credentials.read().await.get(username).cloned())
+    }
+    async fn insert_credential(
+        &self,
+        username: &str,
+        credential: &Credential,
+    ) -> std::io::Result<bool> {
+        match self.credentials.write().await.entry(username.to_owned()) {
+            std::collections::hash_map::Entry::Occupied(_) => Ok(false),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(credential.clone());
+                Ok(true)
+            }
+        }
+    }
+    async fn put_credential(&self, username: &str, credential: &Credential) -> std::io::Result<()> {
+        self.credentials
+            .write()
+            .await
+            .insert(username.to_owned(), credential.clone());
+        Ok(())
+    }
+    async fn list_credentials(&self) -> std::io::Result<Vec<(String, Credential)>> {
+        Ok(self
+            .credentials
+            .read()
+            .await
+            .iter()
+            .map(|(username, credential)| (username.clone(), credential.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,626 @@
+use crate::auth::Credential;
+use crate::blog::{Post, PostID, Revision, User};
+use crate::job::queue::{QueuedJob, QueuedJobID};
+use crate::state::incomplete::IncompletePost;
+use crate::state::session::{Session, SessionHash};
+use crate::state::token::{Token, TokenHash};
+use std::sync::Arc;
+
+pub mod fs;
+pub mod memory;
+pub mod sqlite;
+
+pub type MediaReader = Box<dyn tokio::io::AsyncRead + Send + Unpin>;
+
+/// Which version of an uploaded image to read or write
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, enum_iterator::Sequence)]
+pub enum MediaSize {
+    Raw,
+    Small,
+    Large,
+}
+
+/// Everything the blog persists. Reads return `Ok(None)` when the requested
+/// item doesn't exist, and `Err` only when the backend itself failed.
+#[async_trait::async_trait]
+pub trait Store: std::fmt::Debug + Send + Sync {
+    async fn get_post(&self, post_id: &PostID) -> std::io::Result<Option<Post>>;
+    /// Creates the post if it doesn't exist yet
+    async fn put_post(&self, post: &Post) -> std::io::Result<()>;
+    /// Removes a post along with its text, media and draft state
+    async fn delete_post(&self, post_id: &PostID) -> std::io::Result<()>;
+    async fn list_posts(&self) -> std::io::Result<Vec<PostID>>;
+
+    /// The newest finished, listed top-level posts, newest first. The default goes
+    /// through every user's post list, so backends with an index should
+    /// override it.
+    async fn latest_posts(&self, amount: usize) -> std::io::Result<Vec<Post>> {
+        // `amount` can be far more than there are posts
+        let mut latest_posts: Vec<Post> = Vec::new();
+
+        'users: for user in self.list_users().await? {
+            // user posts are stored in chronological order
+            for post_id in user.posts.into_iter().rev() {
+                let post = match self.get_post(&post_id).await {
+                    Ok(Some(it)) => it,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        eprintln!("Error reading meta for post {post_id}, skipping it: {err}");
+                        continue;
+                    }
+                };
+                if post.in_progress || post.is_reply() || !post.visibility.is_listed() {
+                    continue;
+                }
+
+                if let Some(last_post) = latest_posts.last() {
+                    if post.timestamp < last_post.timestamp {
+                        if latest_posts.len() < amount {
+                            latest_posts.push(post);
+                            continue;
+                        }
+                        continue 'users;
+                    }
+                } else {
+                    latest_posts.push(post);
+                    continue;
+                }
+
+                for cursor in (0..latest_posts.len()).rev() {
+                    if post.timestamp < latest_posts[cursor].timestamp {
+                        latest_posts.insert(cursor, post);
+                        break;
+                    }
+                    if cursor == 0 {
+                        latest_posts.insert(0, post);
+                        break; // redundant break but the compiler isnt smart enough to tell (yet!)
+                    }
+                }
+
+                if latest_posts.len() > amount {
+                    latest_posts.pop();
+                }
+            }
+        }
+
+        Ok(latest_posts)
+    }
+
+    async fn get_incomplete(&self, post_id: &PostID) -> std::io::Result<Option<IncompletePost>>;
+    async fn put_incomplete(&self, post: &IncompletePost) -> std::io::Result<()>;
+    async fn delete_incomplete(&self, post_id: &PostID) -> std::io::Result<()>;
+
+    async fn get_text(&self, post_id: &PostID) -> std::io::Result<Option<String>>;
+    async fn put_text(&self, post_id: &PostID, text: &str) -> std::io::Result<()>;
+
+    /// Ordered by revision number
+    async fn list_revisions(&self, post_id: &PostID) -> std::io::Result<Vec<Revision>>;
+    async fn get_revision(
+        &self,
+        post_id: &PostID,
+        number: u32,
+    ) -> std::io::Result<Option<Revision>>;
+    async fn put_revision(&self, post_id: &PostID, revision: &Revision) -> std::io::Result<()>;
+
+    async fn get_user(&self, username: &str) -> std::io::Result<Option<User>>;
+    async fn put_user(&self, user: &User) -> std::io::Result<()>;
+    async fn list_users(&self) -> std::io::Result<Vec<User>>;
+
+    async fn open_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<MediaReader>>;
+    async fn get_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>>;
+    /// Overwrites the media if it already exists
+    async fn put_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()>;
+    /// Creates the media if it doesn't exist yet
+    async fn append_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()>;
+    async fn delete_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()>;
+    async fn list_media(&self, post_id: &PostID) -> std::io::Result<Vec<(MediaSize, String)>>;
+
+    /// Images belonging to a user rather than a post, like their avatar
+    async fn get_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>>;
+    /// Overwrites the media if it already exists
+    async fn put_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()>;
+    /// Creates the media if it doesn't exist yet
+    async fn append_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()>;
+    async fn delete_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()>;
+    async fn list_user_media(&self, username: &str) -> std::io::Result<Vec<(MediaSize, String)>>;
+
+    async fn list_jobs(&self) -> std::io::Result<Vec<QueuedJob>>;
+    /// Overwrites the job if it already exists
+    async fn put_job(&self, job: &QueuedJob) -> std::io::Result<()>;
+    async fn delete_job(&self, job_id: &QueuedJobID) -> std::io::Result<()>;
+    /// Jobs that ran out of attempts are kept around for inspection
+    async fn list_dead_jobs(&self) -> std::io::Result<Vec<QueuedJob>>;
+    async fn put_dead_job(&self, job: &QueuedJob) -> std::io::Result<()>;
+
+    /// Including expired ones, which are up to the caller to clean up
+    async fn list_sessions(&self) -> std::io::Result<Vec<(SessionHash, Session)>>;
+    /// Overwrites the session if it already exists
+    async fn put_session(&self, hash: &SessionHash, session: &Session) -> std::io::Result<()>;
+    async fn delete_session(&self, hash: &SessionHash) -> std::io::Result<()>;
+
+    async fn list_tokens(&self) -> std::io::Result<Vec<(TokenHash, Token)>>;
+    /// Overwrites the token if it already exists
+    async fn put_token(&self, hash: &TokenHash, token: &Token) -> std::io::Result<()>;
+    async fn delete_token(&self, hash: &TokenHash) -> std::io::Result<()>;
+
+    async fn get_credential(&self, username: &str) -> std::io::Result<Option<Credential>>;
+    /// Adds the credential only if the user doesn't have one yet, returning
+    /// whether it did. Checking and adding happen as one step.
+    async fn insert_credential(
+        &self,
+        username: &str,
+        credential: &Credential,
+    ) -> std::io::Result<bool>;
+    /// Overwrites the credential if it already exists
+    async fn put_credential(&self, username: &str, credential: &Credential) -> std::io::Result<()>;
+    async fn list_credentials(&self) -> std::io::Result<Vec<(String, Credential)>>;
+}
+
+/// Picks the backend from the `BLOG_STORE` environment variable, defaulting to
+/// the file tree at [`crate::blog::STORE_PATH`]. Until the SQLite backend has
+/// a database, the existing file tree is imported into a new one.
+pub async fn from_env() -> std::io::Result<Arc<dyn Store>> {
+    let store_path = std::path::Path::new(crate::blog::STORE_PATH);
+
+    match std::env::var("BLOG_STORE").as_deref() {
+        Ok("memory") => Ok(Arc::new(memory::MemoryStore::default())),
+        Ok("sqlite") => {
+            let database_path = store_path.join("blog.sqlite3");
+
+            if !tokio::fs::try_exists(&database_path).await?
+                && tokio::fs::try_exists(store_path.join("post")).await?
+            {
+                // the database only takes its place once the import is done, so
+                // an import that failed partway is started over next time
+                let import_path = store_path.join("blog.sqlite3.tmp");
+                match tokio::fs::remove_file(&import_path).await {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+                    _ => (),
+                }
+
+                println!("Importing {store_path:?} into {database_path:?}");
+                let fs_store = fs::FsStore::new(store_path);
+                fs_store.migrate_logins().await?;
+                import(&fs_store, &sqlite::SqliteStore::open(&import_path)?).await?;
+                tokio::fs::rename(&import_path, &database_path).await?;
+            }
+
+            Ok(Arc::new(sqlite::SqliteStore::open(&database_path)?))
+        }
+        _ => {
+            let store = fs::FsStore::new(store_path);
+            store.migrate_logins().await?;
+            Ok(Arc::new(store))
+        }
+    }
+}
+
+/// Copies every user, post, draft, text, revision, image, job, session, API token and
+/// credential from one store into another
+pub async fn import(from: &dyn Store, to: &dyn Store) -> std::io::Result<()> {
+    let users = from.list_users().await?;
+    for user in &users {
+        to.put_user(user).await?;
+
+        for (size, name) in from.list_user_media(&user.username).await? {
+            if let Some(data) = from.get_user_media(&user.username, size, &name).await? {
+                to.put_user_media(&user.username, size, &name, &data)
+                    .await?;
+            }
+        }
+    }
+
+    let post_ids = from.list_posts().await?;
+    for post_id in &post_ids {
+        let Some(post) = from.get_post(post_id).await? else {
+            continue;
+        };
+        to.put_post(&post).await?;
+
+        if let Some(incomplete) = from.get_incomplete(post_id).await? {
+            to.put_incomplete(&incomplete).await?;
+        }
+
+        if let Some(text) = from.get_text(post_id).await? {
+            to.put_text(post_id, &text).await?;
+        }
+        for revision in from.list_revisions(post_id).await? {
+            to.put_revision(post_id, &revision).await?;
+        }
+        for (size, name) in from.list_media(post_id).await? {
+            if let Some(data) = from.get_media(post_id, size, &name).await? {
+                to.put_media(post_id, size, &name, &data).await?;
+            }
+        }
+    }
+
+    for job in from.list_jobs().await? {
+        to.put_job(&job).await?;
+    }
+    for job in from.list_dead_jobs().await? {
+        to.put_dead_job(&job).await?;
+    }
+
+    for (hash, session) in from.list_sessions().await? {
+        to.put_session(&hash, &session).await?;
+    }
+    for (hash, token) in from.list_tokens().await? {
+        to.put_token(&hash, &token).await?;
+    }
+
+    let credentials = from.list_credentials().await?;
+    for (username, credential) in &credentials {
+        to.put_credential(username, credential).await?;
+    }
+
+    println!(
+        "Imported {} users, {} posts and {} credentials",
+        users.len(),
+        post_ids.len(),
+        credentials.len()
+    );
+
+    Ok(())
+}
+
+impl MediaSize {
+    pub fn folder_name(self) -> &'static str {
+        match self {
+            MediaSize::Raw => "raw",
+            MediaSize::Small => "small",
+            MediaSize::Large => "large",
+        }
+    }
+    pub fn from_folder_name(folder_name: &str) -> Option<MediaSize> {
+        enum_iterator::all::<MediaSize>().find(|size| size.folder_name() == folder_name)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::blog::{Permissions, Visibility};
+
+    pub fn post(post_id: &str, author_username: &str) -> Post {
+        Post {
+            id: post_id.to_owned(),
+            author_username: author_username.to_owned(),
+            timestamp: chrono::Utc::now(),
+            reply_to: None,
+            replies: Vec::new(),
+            quotes: Vec::new(),
+            in_progress: false,
+            visibility: Visibility::Public,
+            audience: Vec::new(),
+            edited_at: None,
+        }
+    }
+
+    pub fn user(username: &str) -> User {
+        User {
+            username: username.to_owned(),
+            name: username.to_owned(),
+            posts: Vec::new(),
+            permissions: Permissions {
+                can_create_invites: false,
+                can_create_posts: true,
+                can_reset_passwords: false,
+            },
+            members: Default::default(),
+            circles: Default::default(),
+            member_requests: Default::default(),
+            profile: Default::default(),
+        }
+    }
+
+    /// A fresh folder under the system temp dir, removed when dropped
+    pub struct TempDir(pub std::path::PathBuf);
+
+    impl TempDir {
+        pub fn new() -> TempDir {
+            let path = std::env::temp_dir().join(format!(
+                "blog-test-{}",
+                crate::blog::get_random_hex_string::<8>()
+            ));
+            std::fs::create_dir_all(&path).expect("temp dir should be creatable");
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn round_trip_posts(store: &dyn Store) {
+        let mut post = post("post", "author");
+        post.replies = vec!["reply".to_owned()];
+        post.visibility = Visibility::Members;
+        store.put_post(&post).await.unwrap();
+
+        let stored = store.get_post(&post.id).await.unwrap().unwrap();
+        assert_eq!(stored.author_username, "author");
+        assert_eq!(stored.replies, post.replies);
+        assert_eq!(stored.visibility, Visibility::Members);
+        assert_eq!(store.list_posts().await.unwrap(), vec![post.id.clone()]);
+        assert!(store
+            .get_post(&"missing".to_owned())
+            .await
+            .unwrap()
+            .is_none());
+
+        store.put_text(&post.id, "hello").await.unwrap();
+        assert_eq!(
+            store.get_text(&post.id).await.unwrap().as_deref(),
+            Some("hello")
+        );
+
+        store
+            .put_media(&post.id, MediaSize::Raw, "image.png", b"ab")
+            .await
+            .unwrap();
+        store
+            .append_media(&post.id, MediaSize::Raw, "image.png", b"cd")
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get_media(&post.id, MediaSize::Raw, "image.png")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(&b"abcd"[..])
+        );
+        assert_eq!(
+            store.list_media(&post.id).await.unwrap(),
+            vec![(MediaSize::Raw, "image.png".to_owned())]
+        );
+
+        store.delete_post(&post.id).await.unwrap();
+        assert!(store.get_post(&post.id).await.unwrap().is_none());
+        assert!(store.get_text(&post.id).await.unwrap().is_none());
+        assert!(store.list_media(&post.id).await.unwrap().is_empty());
+    }
+
+    async fn round_trip_revisions(store: &dyn Store) {
+        let post_id = "post".to_owned();
+        store.put_post(&post(&post_id, "author")).await.unwrap();
+
+        for number in [2, 1] {
+            let revision = Revision {
+                number,
+                written_at: chrono::Utc::now(),
+                text: format!("revision {number}"),
+            };
+            store.put_revision(&post_id, &revision).await.unwrap();
+        }
+
+        let numbers = store
+            .list_revisions(&post_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|revision| revision.number)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, vec![1, 2]);
+        assert_eq!(
+            store.get_revision(&post_id, 2).await.unwrap().unwrap().text,
+            "revision 2"
+        );
+        assert!(store.get_revision(&post_id, 3).await.unwrap().is_none());
+    }
+
+    async fn round_trip_users(store: &dyn Store) {
+        let mut user = user("someone");
+        user.posts.push("post".to_owned());
+        user.members.insert("friend".to_owned());
+        store.put_user(&user).await.unwrap();
+
+        let stored = store.get_user("someone").await.unwrap().unwrap();
+        assert_eq!(stored.posts, user.posts);
+        assert_eq!(stored.members, user.members);
+        assert_eq!(store.list_users().await.unwrap().len(), 1);
+        assert!(store.get_user("nobody").await.unwrap().is_none());
+    }
+
+    async fn round_trip_sessions(store: &dyn Store) {
+        let now = chrono::Utc::now();
+        let session = Session {
+            for_username: "someone".to_owned(),
+            created_at: now,
+            refreshed_at: None,
+            expires_at: now,
+            last_used_at: None,
+            client: Default::default(),
+            refresh: None,
+        };
+        store
+            .put_session(&"hash".to_owned(), &session)
+            .await
+            .unwrap();
+
+        let sessions = store.list_sessions().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].0, "hash");
+        assert_eq!(sessions[0].1.for_username, "someone");
+
+        store.delete_session(&"hash".to_owned()).await.unwrap();
+        assert!(store.list_sessions().await.unwrap().is_empty());
+    }
+
+    async fn round_trip_credentials(store: &dyn Store) {
+        let now = chrono::Utc::now();
+        let credential = Credential {
+            hash: "first".to_owned(),
+            created_at: now,
+            updated_at: now,
+            failed_attempts: 0,
+            last_failed_at: None,
+            totp: None,
+        };
+        assert!(store
+            .insert_credential("someone", &credential)
+            .await
+            .unwrap());
+
+        let second = Credential {
+            hash: "second".to_owned(),
+            ..credential.clone()
+        };
+        assert!(!store.insert_credential("someone", &second).await.unwrap());
+        assert_eq!(
+            store.get_credential("someone").await.unwrap().unwrap().hash,
+            "first"
+        );
+
+        store.put_credential("someone", &second).await.unwrap();
+        assert_eq!(
+            store.get_credential("someone").await.unwrap().unwrap().hash,
+            "second"
+        );
+        assert_eq!(store.list_credentials().await.unwrap().len(), 1);
+    }
+
+    async fn conformance(new_store: impl Fn() -> Arc<dyn Store>) {
+        round_trip_posts(&*new_store()).await;
+        round_trip_revisions(&*new_store()).await;
+        round_trip_users(&*new_store()).await;
+        round_trip_sessions(&*new_store()).await;
+        round_trip_credentials(&*new_store()).await;
+    }
+
+    #[tokio::test]
+    async fn fs_store_skips_corrupt_files() {
+        let temp_dir = TempDir::new();
+        std::fs::create_dir_all(temp_dir.0.join("user")).unwrap();
+        let store = fs::FsStore::new(&temp_dir.0);
+
+        let mut author = user("author");
+        author.posts = vec!["good".to_owned(), "bad".to_owned()];
+        store.put_user(&author).await.unwrap();
+        store.put_post(&post("good", "author")).await.unwrap();
+        store.put_post(&post("bad", "author")).await.unwrap();
+        std::fs::write(temp_dir.0.join("post/bad/meta.json"), "{").unwrap();
+        std::fs::write(temp_dir.0.join("user/broken.json"), "{").unwrap();
+
+        assert_eq!(store.list_users().await.unwrap().len(), 1);
+        let latest = store.latest_posts(10).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].id, "good");
+
+        for folder in [
+            "session",
+            "token",
+            "job",
+            "credential",
+            "post/good/revision",
+        ] {
+            std::fs::create_dir_all(temp_dir.0.join(folder)).unwrap();
+            std::fs::write(temp_dir.0.join(folder).join("torn.json"), "{").unwrap();
+        }
+        assert!(store.list_sessions().await.unwrap().is_empty());
+        assert!(store.list_tokens().await.unwrap().is_empty());
+        assert!(store.list_jobs().await.unwrap().is_empty());
+        assert!(store.list_credentials().await.unwrap().is_empty());
+        assert!(store
+            .list_revisions(&"good".to_owned())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn fs_store_refuses_names_outside_its_folders() {
+        let temp_dir = TempDir::new();
+        let store = fs::FsStore::new(temp_dir.0.join("store"));
+        let post_id = "post".to_owned();
+
+        for name in ["", "..", "../escaped", "a/b", "a\\b"] {
+            assert!(store.get_user(name).await.is_err(), "{name:?}");
+            assert!(store.get_credential(name).await.is_err(), "{name:?}");
+            assert!(store.get_post(&name.to_owned()).await.is_err(), "{name:?}");
+            assert!(store
+                .append_media(&post_id, MediaSize::Raw, name, b"data")
+                .await
+                .is_err());
+            assert!(store
+                .put_user_media("someone", MediaSize::Raw, name, b"data")
+                .await
+                .is_err());
+        }
+        assert!(!temp_dir.0.join("escaped").exists());
+    }
+
+    #[tokio::test]
+    async fn memory_store_conforms() {
+        conformance(|| Arc::new(memory::MemoryStore::default())).await;
+    }
+
+    #[tokio::test]
+    async fn fs_store_conforms() {
+        let temp_dirs = std::sync::Mutex::new(Vec::new());
+        conformance(|| {
+            let temp_dir = TempDir::new();
+            std::fs::create_dir_all(temp_dir.0.join("user")).unwrap();
+            let store = Arc::new(fs::FsStore::new(&temp_dir.0));
+            temp_dirs.lock().unwrap().push(temp_dir);
+            store
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_conforms() {
+        conformance(|| Arc::new(sqlite::SqliteStore::open(":memory:").unwrap())).await;
+    }
+}
@@ -0,0 +1,880 @@
+use super::{MediaReader, MediaSize};
+use crate::auth::Credential;
+use crate::blog::{Post, PostID, Revision, User, Visibility};
+use crate::job::queue::{QueuedJob, QueuedJobID};
+use crate::state::incomplete::IncompletePost;
+use crate::state::session::{Session, SessionHash};
+use crate::state::token::{Token, TokenHash};
+use rusqlite::{params, OptionalExtension};
+use std::sync::{Arc, Mutex};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    username TEXT PRIMARY KEY NOT NULL,
+    -- the whole `blog::User` as json, since it's only ever looked up by name
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS posts (
+    id TEXT PRIMARY KEY NOT NULL,
+    author_username TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    reply_to TEXT,
+    in_progress INTEGER NOT NULL,
+    is_private INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS posts_latest ON posts (in_progress, reply_to, timestamp);
+CREATE TABLE IF NOT EXISTS replies (
+    parent_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    reply_id TEXT NOT NULL,
+    PRIMARY KEY (parent_id, position)
+);
+CREATE TABLE IF NOT EXISTS quotes (
+    post_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    quote_id TEXT NOT NULL,
+    PRIMARY KEY (post_id, position)
+);
+CREATE TABLE IF NOT EXISTS incomplete_posts (
+    post_id TEXT PRIMARY KEY NOT NULL,
+    -- `state::incomplete::IncompletePost` as json
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS texts (
+    post_id TEXT PRIMARY KEY NOT NULL,
+    text TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS revisions (
+    post_id TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    written_at TEXT NOT NULL,
+    text TEXT NOT NULL,
+    PRIMARY KEY (post_id, number)
+);
+CREATE TABLE IF NOT EXISTS media (
+    post_id TEXT NOT NULL,
+    size TEXT NOT NULL,
+    name TEXT NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (post_id, size, name)
+);
+CREATE TABLE IF NOT EXISTS user_media (
+    username TEXT NOT NULL,
+    size TEXT NOT NULL,
+    name TEXT NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY (username, size, name)
+);
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY NOT NULL,
+    -- `job::queue::QueuedJob` as json
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS dead_jobs (
+    id TEXT PRIMARY KEY NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sessions (
+    hash TEXT PRIMARY KEY NOT NULL,
+    -- `state::session::Session` as json
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tokens (
+    hash TEXT PRIMARY KEY NOT NULL,
+    -- `state::token::Token` as json
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS credentials (
+    username TEXT PRIMARY KEY NOT NULL,
+    hash TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    failed_attempts INTEGER NOT NULL,
+    last_failed_at TEXT
+);
+";
+
+/// Changes to tables that existed before, applied in order on top of
+/// [`SCHEMA`]. `PRAGMA user_version` records how many have already run.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE posts ADD COLUMN edited_at TEXT;",
+    "ALTER TABLE posts ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
+    UPDATE posts SET visibility = 'members' WHERE is_private;
+    ALTER TABLE posts DROP COLUMN is_private;",
+    // `blog::Post::audience` as json
+    "ALTER TABLE posts ADD COLUMN audience TEXT NOT NULL DEFAULT '[]';",
+    // the old table only had the hash, so the timestamps are the migration's
+    "CREATE TABLE IF NOT EXISTS logins (username TEXT PRIMARY KEY NOT NULL, hash TEXT NOT NULL);
+    INSERT OR IGNORE INTO credentials
+        SELECT username, hash, datetime('now'), datetime('now'), 0, NULL
+        FROM logins;
+    DROP TABLE logins;",
+    // `auth::Totp` as json
+    "ALTER TABLE credentials ADD COLUMN totp TEXT;",
+];
+
+/// A single SQLite database file. Queries run on the blocking thread pool
+/// behind one shared connection.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<rusqlite::Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<SqliteStore> {
+        let mut connection = rusqlite::Connection::open(path).map_err(std::io::Error::other)?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(std::io::Error::other)?;
+        migrate(&mut connection).map_err(std::io::Error::other)?;
+
+        Ok(SqliteStore {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    async fn call<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&mut rusqlite::Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> std::io::Result<T> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .expect("sqlite connection should not be poisoned");
+            query(&mut connection)
+        })
+        .await
+        .expect("task should not panic")
+        .map_err(std::io::Error::other)
+    }
+}
+
+fn migrate(connection: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    let version: usize =
+        transaction.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+
+    for migration in MIGRATIONS.iter().skip(version) {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len() as i64)?;
+    transaction.commit()
+}
+
+fn read_post(
+    connection: &rusqlite::Connection,
+    post_id: &PostID,
+) -> rusqlite::Result<Option<Post>> {
+    let Some(mut post) = connection
+        .query_row(
+            "SELECT author_username, timestamp, reply_to, in_progress, visibility, audience,
+                edited_at
+            FROM posts WHERE id = ?1",
+            params![post_id],
+            |row| {
+                Ok(Post {
+                    id: post_id.clone(),
+                    author_username: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    reply_to: row.get(2)?,
+                    replies: Vec::new(),
+                    quotes: Vec::new(),
+                    in_progress: row.get(3)?,
+                    visibility: read_visibility(row, 4)?,
+                    audience: from_json(row.get(5)?)?,
+                    edited_at: row.get(6)?,
+                })
+            },
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    post.replies = connection
+        .prepare_cached("SELECT reply_id FROM replies WHERE parent_id = ?1 ORDER BY position")?
+        .query_map(params![post_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    post.quotes = connection
+        .prepare_cached("SELECT quote_id FROM quotes WHERE post_id = ?1 ORDER BY position")?
+        .query_map(params![post_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(Some(post))
+}
+
+fn read_visibility(row: &rusqlite::Row, index: usize) -> rusqlite::Result<Visibility> {
+    let name = row.get::<_, String>(index)?;
+    Visibility::from_name(&name).ok_or_else(|| {
+        rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Text,
+            format!("unknown visibility {name}").into(),
+        )
+    })
+}
+
+fn read_credential(row: &rusqlite::Row) -> rusqlite::Result<Credential> {
+    Ok(Credential {
+        hash: row.get(0)?,
+        created_at: row.get(1)?,
+        updated_at: row.get(2)?,
+        failed_attempts: row.get(3)?,
+        last_failed_at: row.get(4)?,
+        totp: row
+            .get::<_, Option<String>>(5)?
+            .map(from_json)
+            .transpose()?,
+    })
+}
+
+fn read_revision(row: &rusqlite::Row) -> rusqlite::Result<Revision> {
+    Ok(Revision {
+        number: row.get(0)?,
+        written_at: row.get(1)?,
+        text: row.get(2)?,
+    })
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(data: String) -> rusqlite::Result<T> {
+    serde_json::from_str(&data).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(err))
+    })
+}
+
+#[async_trait::async_trait]
+impl super::Store for SqliteStore {
+    async fn get_post(&self, post_id: &PostID) -> std::io::Result<Option<Post>> {
+        let post_id = post_id.clone();
+        self.call(move |connection| read_post(connection, &post_id))
+            .await
+    }
+    async fn put_post(&self, post: &Post) -> std::io::Result<()> {
+        let post = post.clone();
+        self.call(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute(
+                "INSERT INTO posts
+                    (id, author_username, timestamp, reply_to, in_progress, visibility, audience,
+                    edited_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ON CONFLICT (id) DO UPDATE SET
+                    author_username = excluded.author_username,
+                    timestamp = excluded.timestamp,
+                    reply_to = excluded.reply_to,
+                    in_progress = excluded.in_progress,
+                    visibility = excluded.visibility,
+                    audience = excluded.audience,
+                    edited_at = excluded.edited_at",
+                params![
+                    post.id,
+                    post.author_username,
+                    post.timestamp,
+                    post.reply_to,
+                    post.in_progress,
+                    post.visibility.name(),
+                    serde_json::to_string(&post.audience).expect("audience should serialize"),
+                    post.edited_at
+                ],
+            )?;
+
+            transaction.execute("DELETE FROM replies WHERE parent_id = ?1", params![post.id])?;
+            for (position, reply_id) in post.replies.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO replies (parent_id, position, reply_id) VALUES (?1, ?2, ?3)",
+                    params![post.id, position as i64, reply_id],
+                )?;
+            }
+            transaction.execute("DELETE FROM quotes WHERE post_id = ?1", params![post.id])?;
+            for (position, quote_id) in post.quotes.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO quotes (post_id, position, quote_id) VALUES (?1, ?2, ?3)",
+                    params![post.id, position as i64, quote_id],
+                )?;
+            }
+
+            transaction.commit()
+        })
+        .await
+    }
+    async fn delete_post(&self, post_id: &PostID) -> std::io::Result<()> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            let transaction = connection.transaction()?;
+            transaction.execute("DELETE FROM posts WHERE id = ?1", params![post_id])?;
+            transaction.execute("DELETE FROM replies WHERE parent_id = ?1", params![post_id])?;
+            transaction.execute("DELETE FROM quotes WHERE post_id = ?1", params![post_id])?;
+            transaction.execute(
+                "DELETE FROM incomplete_posts WHERE post_id = ?1",
+                params![post_id],
+            )?;
+            transaction.execute("DELETE FROM texts WHERE post_id = ?1", params![post_id])?;
+            transaction.execute("DELETE FROM revisions WHERE post_id = ?1", params![post_id])?;
+            transaction.execute("DELETE FROM media WHERE post_id = ?1", params![post_id])?;
+            transaction.commit()
+        })
+        .await
+    }
+    async fn list_posts(&self) -> std::io::Result<Vec<PostID>> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT id FROM posts")?
+                .query_map([], |row| row.get(0))?
+                .collect()
+        })
+        .await
+    }
+    async fn latest_posts(&self, amount: usize) -> std::io::Result<Vec<Post>> {
+        self.call(move |connection| {
+            let post_ids = connection
+                .prepare_cached(
+                    "SELECT id FROM posts
+                    WHERE in_progress = 0 AND reply_to IS NULL AND visibility != 'unlisted'
+                    ORDER BY timestamp DESC LIMIT ?1",
+                )?
+                .query_map(params![i64::try_from(amount).unwrap_or(i64::MAX)], |row| {
+                    row.get::<_, PostID>(0)
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            let mut posts = Vec::with_capacity(post_ids.len());
+            for post_id in post_ids {
+                posts.extend(read_post(connection, &post_id)?);
+            }
+            Ok(posts)
+        })
+        .await
+    }
+
+    async fn get_incomplete(&self, post_id: &PostID) -> std::io::Result<Option<IncompletePost>> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM incomplete_posts WHERE post_id = ?1",
+                    params![post_id],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .map(from_json)
+                .transpose()
+        })
+        .await
+    }
+    async fn put_incomplete(&self, post: &IncompletePost) -> std::io::Result<()> {
+        let post_id = post.meta.id.clone();
+        let data = serde_json::to_string(post).expect("incomplete post should serialize");
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO incomplete_posts (post_id, data) VALUES (?1, ?2)",
+                params![post_id, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_incomplete(&self, post_id: &PostID) -> std::io::Result<()> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            connection.execute(
+                "DELETE FROM incomplete_posts WHERE post_id = ?1",
+                params![post_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_text(&self, post_id: &PostID) -> std::io::Result<Option<String>> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT text FROM texts WHERE post_id = ?1",
+                    params![post_id],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await
+    }
+    async fn put_text(&self, post_id: &PostID, text: &str) -> std::io::Result<()> {
+        let (post_id, text) = (post_id.clone(), text.to_owned());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO texts (post_id, text) VALUES (?1, ?2)",
+                params![post_id, text],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_revisions(&self, post_id: &PostID) -> std::io::Result<Vec<Revision>> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            connection
+                .prepare_cached(
+                    "SELECT number, written_at, text FROM revisions
+                    WHERE post_id = ?1 ORDER BY number",
+                )?
+                .query_map(params![post_id], read_revision)?
+                .collect()
+        })
+        .await
+    }
+    async fn get_revision(
+        &self,
+        post_id: &PostID,
+        number: u32,
+    ) -> std::io::Result<Option<Revision>> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT number, written_at, text FROM revisions
+                    WHERE post_id = ?1 AND number = ?2",
+                    params![post_id, number],
+                    read_revision,
+                )
+                .optional()
+        })
+        .await
+    }
+    async fn put_revision(&self, post_id: &PostID, revision: &Revision) -> std::io::Result<()> {
+        let (post_id, revision) = (post_id.clone(), revision.clone());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO revisions (post_id, number, written_at, text)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![post_id, revision.number, revision.written_at, revision.text],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user(&self, username: &str) -> std::io::Result<Option<User>> {
+        let username = username.to_owned();
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM users WHERE username = ?1",
+                    params![username],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .map(from_json)
+                .transpose()
+        })
+        .await
+    }
+    async fn put_user(&self, user: &User) -> std::io::Result<()> {
+        let username = user.username.clone();
+        let data = serde_json::to_string(user).expect("user should serialize");
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO users (username, data) VALUES (?1, ?2)",
+                params![username, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn list_users(&self) -> std::io::Result<Vec<User>> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT data FROM users")?
+                .query_map([], |row| row.get(0))?
+                .map(|data| from_json(data?))
+                .collect()
+        })
+        .await
+    }
+
+    async fn open_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<MediaReader>> {
+        let media = self.get_media(post_id, size, name).await?;
+        Ok(media.map(|data| Box::new(std::io::Cursor::new(data)) as MediaReader))
+    }
+    async fn get_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        let (post_id, name) = (post_id.clone(), name.to_owned());
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM media WHERE post_id = ?1 AND size = ?2 AND name = ?3",
+                    params![post_id, size.folder_name(), name],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await
+    }
+    async fn put_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let (post_id, name, data) = (post_id.clone(), name.to_owned(), data.to_vec());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO media (post_id, size, name, data) VALUES (?1, ?2, ?3, ?4)",
+                params![post_id, size.folder_name(), name, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn append_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let (post_id, name, data) = (post_id.clone(), name.to_owned(), data.to_vec());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT INTO media (post_id, size, name, data) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (post_id, size, name) DO UPDATE SET data = CAST(data || excluded.data AS BLOB)",
+                params![post_id, size.folder_name(), name, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_media(
+        &self,
+        post_id: &PostID,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()> {
+        let (post_id, name) = (post_id.clone(), name.to_owned());
+        self.call(move |connection| {
+            connection.execute(
+                "DELETE FROM media WHERE post_id = ?1 AND size = ?2 AND name = ?3",
+                params![post_id, size.folder_name(), name],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn list_media(&self, post_id: &PostID) -> std::io::Result<Vec<(MediaSize, String)>> {
+        let post_id = post_id.clone();
+        self.call(move |connection| {
+            connection
+                .prepare("SELECT size, name FROM media WHERE post_id = ?1")?
+                .query_map(params![post_id], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .filter_map(|media| match media {
+                    Ok((size, name)) => Some(Ok((MediaSize::from_folder_name(&size)?, name))),
+                    Err(err) => Some(Err(err)),
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn get_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        let (username, name) = (username.to_owned(), name.to_owned());
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT data FROM user_media WHERE username = ?1 AND size = ?2 AND name = ?3",
+                    params![username, size.folder_name(), name],
+                    |row| row.get(0),
+                )
+                .optional()
+        })
+        .await
+    }
+    async fn put_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let (username, name, data) = (username.to_owned(), name.to_owned(), data.to_vec());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO user_media (username, size, name, data)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![username, size.folder_name(), name, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn append_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        let (username, name, data) = (username.to_owned(), name.to_owned(), data.to_vec());
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT INTO user_media (username, size, name, data) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (username, size, name) DO UPDATE SET data = CAST(data || excluded.data AS BLOB)",
+                params![username, size.folder_name(), name, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_user_media(
+        &self,
+        username: &str,
+        size: MediaSize,
+        name: &str,
+    ) -> std::io::Result<()> {
+        let (username, name) = (username.to_owned(), name.to_owned());
+        self.call(move |connection| {
+            connection.execute(
+                "DELETE FROM user_media WHERE username = ?1 AND size = ?2 AND name = ?3",
+                params![username, size.folder_name(), name],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn list_user_media(&self, username: &str) -> std::io::Result<Vec<(MediaSize, String)>> {
+        let username = username.to_owned();
+        self.call(move |connection| {
+            connection
+                .prepare("SELECT size, name FROM user_media WHERE username = ?1")?
+                .query_map(params![username], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .filter_map(|media| match media {
+                    Ok((size, name)) => Some(Ok((MediaSize::from_folder_name(&size)?, name))),
+                    Err(err) => Some(Err(err)),
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn list_jobs(&self) -> std::io::Result<Vec<QueuedJob>> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT data FROM jobs")?
+                .query_map([], |row| row.get(0))?
+                .map(|data| from_json(data?))
+                .collect()
+        })
+        .await
+    }
+    async fn put_job(&self, job: &QueuedJob) -> std::io::Result<()> {
+        let job_id = job.id.clone();
+        let data = serde_json::to_string(job).expect("job should serialize");
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO jobs (id, data) VALUES (?1, ?2)",
+                params![job_id, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_job(&self, job_id: &QueuedJobID) -> std::io::Result<()> {
+        let job_id = job_id.clone();
+        self.call(move |connection| {
+            connection.execute("DELETE FROM jobs WHERE id = ?1", params![job_id])?;
+            Ok(())
+        })
+        .await
+    }
+    async fn list_dead_jobs(&self) -> std::io::Result<Vec<QueuedJob>> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT data FROM dead_jobs")?
+                .query_map([], |row| row.get(0))?
+                .map(|data| from_json(data?))
+                .collect()
+        })
+        .await
+    }
+    async fn put_dead_job(&self, job: &QueuedJob) -> std::io::Result<()> {
+        let job_id = job.id.clone();
+        let data = serde_json::to_string(job).expect("job should serialize");
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO dead_jobs (id, data) VALUES (?1, ?2)",
+                params![job_id, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_sessions(&self) -> std::io::Result<Vec<(SessionHash, Session)>> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT hash, data FROM sessions")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .map(|session| {
+                    let (hash, data) = session?;
+                    Ok((hash, from_json(data)?))
+                })
+                .collect()
+        })
+        .await
+    }
+    async fn put_session(&self, hash: &SessionHash, session: &Session) -> std::io::Result<()> {
+        let hash = hash.clone();
+        let data = serde_json::to_string(session).expect("session should serialize");
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO sessions (hash, data) VALUES (?1, ?2)",
+                params![hash, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_session(&self, hash: &SessionHash) -> std::io::Result<()> {
+        let hash = hash.clone();
+        self.call(move |connection| {
+            connection.execute("DELETE FROM sessions WHERE hash = ?1", params![hash])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_tokens(&self) -> std::io::Result<Vec<(TokenHash, Token)>> {
+        self.call(|connection| {
+            connection
+                .prepare("SELECT hash, data FROM tokens")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .map(|token| {
+                    let (hash, data) = token?;
+                    Ok((hash, from_json(data)?))
+                })
+                .collect()
+        })
+        .await
+    }
+    async fn put_token(&self, hash: &TokenHash, token: &Token) -> std::io::Result<()> {
+        let hash = hash.clone();
+        let data = serde_json::to_string(token).expect("token should serialize");
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO tokens (hash, data) VALUES (?1, ?2)",
+                params![hash, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn delete_token(&self, hash: &TokenHash) -> std::io::Result<()> {
+        let hash = hash.clone();
+        self.call(move |connection| {
+            connection.execute("DELETE FROM tokens WHERE hash = ?1", params![hash])?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_credential(&self, username: &str) -> std::io::Result<Option<Credential>> {
+        let username = username.to_owned();
+        self.call(move |connection| {
+            connection
+                .query_row(
+                    "SELECT hash, created_at, updated_at, failed_attempts, last_failed_at, totp
+                    FROM credentials WHERE username = ?1",
+                    params![username],
+                    read_credential,
+                )
+                .optional()
+        })
+        .await
+    }
+    async fn insert_credential(
+        &self,
+        username: &str,
+        credential: &Credential,
+    ) -> std::io::Result<bool> {
+        let (username, credential) = (username.to_owned(), credential.clone());
+        let totp = credential
+            .totp
+            .as_ref()
+            .map(|totp| serde_json::to_string(totp).expect("totp should serialize"));
+        self.call(move |connection| {
+            let changes = connection.execute(
+                "INSERT OR IGNORE INTO credentials
+                    (username, hash, created_at, updated_at, failed_attempts, last_failed_at, totp)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    username,
+                    credential.hash,
+                    credential.created_at,
+                    credential.updated_at,
+                    credential.failed_attempts,
+                    credential.last_failed_at,
+                    totp
+                ],
+            )?;
+            Ok(changes == 1)
+        })
+        .await
+    }
+    async fn put_credential(&self, username: &str, credential: &Credential) -> std::io::Result<()> {
+        let (username, credential) = (username.to_owned(), credential.clone());
+        let totp = credential
+            .totp
+            .as_ref()
+            .map(|totp| serde_json::to_string(totp).expect("totp should serialize"));
+        self.call(move |connection| {
+            connection.execute(
+                "INSERT OR REPLACE INTO credentials
+                    (username, hash, created_at, updated_at, failed_attempts, last_failed_at, totp)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    username,
+                    credential.hash,
+                    credential.created_at,
+                    credential.updated_at,
+                    credential.failed_attempts,
+                    credential.last_failed_at,
+                    totp
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+    async fn list_credentials(&self) -> std::io::Result<Vec<(String, Credential)>> {
+        self.call(|connection| {
+            connection
+                .prepare(
+                    "SELECT hash, created_at, updated_at, failed_attempts, last_failed_at, totp,
+                        username
+                    FROM credentials",
+                )?
+                .query_map([], |row| Ok((row.get(6)?, read_credential(row)?)))?
+                .collect()
+        })
+        .await
+    }
+}